@@ -0,0 +1,26 @@
+// Thin wrapper around the WebAssembly build of the compiler, generated in `pkg` by
+// `wasm-pack build --target web -- --features wasm`.
+import init, { compile_circom, compile_circom_files } from "../pkg/circom_2_arithc.js";
+
+let initialized;
+
+/** Loads the WebAssembly module, only once. */
+function load() {
+  initialized ??= init();
+  return initialized;
+}
+
+/**
+ * Compiles a circom program declaring its main component, returning its arithmetic circuit as a JSON object.
+ * The files it includes are given as an object mapping their paths to their sources, includes being resolved
+ * relative to the including file. Compilation errors are thrown as strings.
+ */
+export async function compileCircom(source, includes = {}) {
+  await load();
+  if (Object.keys(includes).length === 0) {
+    return compile_circom(source);
+  }
+
+  const mainFile = "main.circom";
+  return compile_circom_files({ ...includes, [mainFile]: source }, mainFile);
+}
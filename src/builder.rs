@@ -0,0 +1,166 @@
+//! # Builder Module
+//!
+//! This module provides a builder to construct arithmetic circuits from Rust, without a circom program.
+
+use crate::{
+    circuit::{AGateType, ArithmeticCircuit, CircuitError},
+    runtime::generate_u32,
+};
+use num_bigint_dig::BigInt;
+
+/// Handle to a signal of a circuit under construction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SignalHandle(u32);
+
+impl SignalHandle {
+    /// Gets the id of the signal in the circuit.
+    pub fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Builds an arithmetic circuit gate by gate, each operation returning a handle to its output signal.
+#[derive(Debug, Default)]
+pub struct CircuitBuilder {
+    circuit: ArithmeticCircuit,
+}
+
+impl CircuitBuilder {
+    /// Creates a builder for an empty circuit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an input signal, in declaration order.
+    pub fn input(&mut self) -> Result<SignalHandle, CircuitError> {
+        let signal = self.signal()?;
+        self.circuit.mark_input(signal.0)?;
+        Ok(signal)
+    }
+
+    /// Marks a signal as an output, in declaration order.
+    pub fn output(&mut self, signal: SignalHandle) -> Result<(), CircuitError> {
+        self.circuit.mark_output(signal.0)
+    }
+
+    /// Adds a constant signal, reusing the signal of a constant with the same value.
+    pub fn constant(&mut self, value: BigInt) -> Result<SignalHandle, CircuitError> {
+        self.circuit.add_const(value).map(SignalHandle)
+    }
+
+    /// Adds a gate of the given type, returning its output signal.
+    pub fn gate(
+        &mut self,
+        gate_type: AGateType,
+        lhs: SignalHandle,
+        rhs: SignalHandle,
+    ) -> Result<SignalHandle, CircuitError> {
+        let output = self.signal()?;
+        self.circuit
+            .add_gate(gate_type, lhs.0, Some(rhs.0), output.0)?;
+        Ok(output)
+    }
+
+    /// Adds a unary gate of the given type, returning its output signal.
+    pub fn unary_gate(
+        &mut self,
+        gate_type: AGateType,
+        input: SignalHandle,
+    ) -> Result<SignalHandle, CircuitError> {
+        let output = self.signal()?;
+        self.circuit.add_gate(gate_type, input.0, None, output.0)?;
+        Ok(output)
+    }
+
+    /// Adds the gate `a + b`.
+    pub fn add(&mut self, a: SignalHandle, b: SignalHandle) -> Result<SignalHandle, CircuitError> {
+        self.gate(AGateType::AAdd, a, b)
+    }
+
+    /// Adds the gate `a - b`.
+    pub fn sub(&mut self, a: SignalHandle, b: SignalHandle) -> Result<SignalHandle, CircuitError> {
+        self.gate(AGateType::ASub, a, b)
+    }
+
+    /// Adds the gate `a * b`.
+    pub fn mul(&mut self, a: SignalHandle, b: SignalHandle) -> Result<SignalHandle, CircuitError> {
+        self.gate(AGateType::AMul, a, b)
+    }
+
+    /// Adds the gate `a / b`.
+    pub fn div(&mut self, a: SignalHandle, b: SignalHandle) -> Result<SignalHandle, CircuitError> {
+        self.gate(AGateType::ADiv, a, b)
+    }
+
+    /// Adds the gate `-a`.
+    pub fn neg(&mut self, a: SignalHandle) -> Result<SignalHandle, CircuitError> {
+        self.unary_gate(AGateType::ANeg, a)
+    }
+
+    /// Adds the gate `1 / a`.
+    pub fn inv(&mut self, a: SignalHandle) -> Result<SignalHandle, CircuitError> {
+        self.unary_gate(AGateType::AInv, a)
+    }
+
+    /// Constrains two signals to be equal, like `a === b`.
+    pub fn constrain(&mut self, a: SignalHandle, b: SignalHandle) -> Result<(), CircuitError> {
+        self.circuit.add_constraint(a.0, b.0)
+    }
+
+    /// Returns the built circuit.
+    pub fn build(self) -> ArithmeticCircuit {
+        self.circuit
+    }
+
+    /// Adds a signal with a fresh id.
+    fn signal(&mut self) -> Result<SignalHandle, CircuitError> {
+        let mut id = generate_u32();
+        while self.circuit.contains_var(&id) {
+            id = generate_u32();
+        }
+        self.circuit.add_signal(id)?;
+        Ok(SignalHandle(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::test_utils::bn254_prime;
+    use std::collections::HashMap;
+
+    #[test]
+    fn builds_a_circuit_that_simulates() {
+        // (a + b) * c - 2, with a === b
+        let mut builder = CircuitBuilder::new();
+        let (a, b, c) = (
+            builder.input().unwrap(),
+            builder.input().unwrap(),
+            builder.input().unwrap(),
+        );
+        let sum = builder.add(a, b).unwrap();
+        let product = builder.mul(sum, c).unwrap();
+        let two = builder.constant(BigInt::from(2)).unwrap();
+        assert_eq!(builder.constant(BigInt::from(2)).unwrap(), two);
+        let out = builder.sub(product, two).unwrap();
+        builder.output(out).unwrap();
+        builder.constrain(a, b).unwrap();
+        let circuit = builder.build();
+
+        assert_eq!(circuit.get_inputs(), [a.id(), b.id(), c.id()]);
+        assert_eq!(circuit.get_outputs(), [out.id()]);
+        assert_eq!(circuit.gate_count(), 3);
+
+        let prime = bn254_prime();
+        let inputs = |values: [i64; 3]| -> HashMap<u32, BigInt> {
+            [a, b, c]
+                .iter()
+                .zip(values)
+                .map(|(signal, value)| (signal.id(), BigInt::from(value)))
+                .collect()
+        };
+        let values = circuit.simulate(&inputs([3, 3, 4]), &prime).unwrap();
+        assert_eq!(values[&out.id()], BigInt::from(22));
+        assert!(circuit.simulate(&inputs([3, 4, 4]), &prime).is_err());
+    }
+}
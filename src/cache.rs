@@ -0,0 +1,200 @@
+//! # Cache Module
+//!
+//! This module caches the sub-circuits of template instances, to reuse them across compilations.
+
+use crate::{
+    circuit::{ArithmeticCircuit, CircuitError, CircuitOp},
+    program::ProgramError,
+    runtime::Signal,
+};
+use num_bigint_dig::BigInt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+/// Circuit built by a template instance, with the signals of the resulting component.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubCircuit {
+    ops: Vec<CircuitOp>,
+    signals: HashMap<String, Signal>,
+}
+
+impl SubCircuit {
+    /// Creates a sub-circuit from the operations recorded while instantiating a template.
+    /// Returns `None` if the operations or the component signals refer to signals declared outside of the recording,
+    /// such as the ones of shared template instances, since they can't be replayed in another circuit.
+    pub fn new(ops: Vec<CircuitOp>, signals: HashMap<String, Signal>) -> Option<Self> {
+        let mut declared = HashSet::new();
+        for op in &ops {
+            let used = match op {
+                CircuitOp::AddSignal(id) | CircuitOp::AddConst(_, id) => {
+                    declared.insert(*id);
+                    vec![]
+                }
+                CircuitOp::AddGate(_, lhs_id, rhs_id, output_id) => {
+                    [*lhs_id, *output_id].into_iter().chain(*rhs_id).collect()
+                }
+                CircuitOp::AddCustomGate(_, inputs, output) => {
+                    inputs.iter().chain([output]).copied().collect()
+                }
+                CircuitOp::AddConnection(a, b)
+                | CircuitOp::AddConstraint(a, b)
+                | CircuitOp::MergeSignals(a, b) => vec![*a, *b],
+                CircuitOp::RegisterSignalName(id, _) => vec![*id],
+                CircuitOp::SetHintMode(_) => vec![],
+            };
+            if !used.iter().all(|id| declared.contains(id)) {
+                return None;
+            }
+        }
+
+        if !signals
+            .values()
+            .flat_map(|signal| signal.get_ids())
+            .all(|id| declared.contains(&id))
+        {
+            return None;
+        }
+
+        Some(Self { ops, signals })
+    }
+
+    /// Grafts the sub-circuit into a circuit with fresh signal ids, returning the remapped component signals.
+    pub fn graft(
+        &self,
+        circuit: &mut ArithmeticCircuit,
+    ) -> Result<HashMap<String, Signal>, CircuitError> {
+        let ids = circuit.replay(&self.ops)?;
+
+        self.signals
+            .iter()
+            .map(|(name, signal)| {
+                let signal = signal
+                    .remap(&ids)
+                    .ok_or(CircuitError::VariableNotDeclared)?;
+                Ok((name.clone(), signal))
+            })
+            .collect()
+    }
+}
+
+/// Cache of the sub-circuits of template instances, by template name and parameter values.
+/// Entries are only valid for the program they were built from, and are dropped when the field prime changes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CompilationCache {
+    prime: Option<BigInt>,
+    templates: HashMap<(String, Vec<BigInt>), SubCircuit>,
+}
+
+impl CompilationCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a cache written by `save`.
+    pub fn load(path: &Path) -> Result<Self, ProgramError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(bincode::deserialize_from(reader)?)
+    }
+
+    /// Writes the cache to a file.
+    pub fn save(&self, path: &Path) -> Result<(), ProgramError> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(bincode::serialize_into(writer, self)?)
+    }
+
+    /// Sets the field prime of the cached sub-circuits, clearing the cache if it was built for another one.
+    pub fn set_prime(&mut self, prime: &BigInt) {
+        if self.prime.as_ref() != Some(prime) {
+            self.templates.clear();
+            self.prime = Some(prime.clone());
+        }
+    }
+
+    /// Returns the sub-circuit of a template instance with the given parameters.
+    pub fn get(&self, name: &str, params: &[BigInt]) -> Option<&SubCircuit> {
+        self.templates.get(&(name.to_string(), params.to_vec()))
+    }
+
+    /// Caches the sub-circuit of a template instance with the given parameters.
+    pub fn insert(&mut self, name: &str, params: &[BigInt], sub_circuit: SubCircuit) {
+        self.templates
+            .insert((name.to_string(), params.to_vec()), sub_circuit);
+    }
+
+    /// Returns the number of cached sub-circuits.
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Checks if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::{test_utils::bn254_prime, AGateType},
+        runtime::{generate_u32, NestedValue},
+    };
+    use std::{env, fs};
+
+    /// Records the sub-circuit `out = a * b`.
+    fn product_sub_circuit() -> SubCircuit {
+        let mut circuit = ArithmeticCircuit::new();
+        circuit.start_recording();
+        for id in 0..3 {
+            circuit.add_signal(id).unwrap();
+        }
+        circuit.add_gate(AGateType::AMul, 0, Some(1), 2).unwrap();
+        let ops = circuit.stop_recording();
+        let signals = HashMap::from([
+            ("a".to_string(), Signal::from_value(NestedValue::Value(0))),
+            ("b".to_string(), Signal::from_value(NestedValue::Value(1))),
+            ("out".to_string(), Signal::from_value(NestedValue::Value(2))),
+        ]);
+
+        SubCircuit::new(ops, signals).unwrap()
+    }
+
+    #[test]
+    fn saved_caches_load_and_graft() {
+        let prime = bn254_prime();
+        let params = [BigInt::from(2)];
+        let mut cache = CompilationCache::new();
+        cache.set_prime(&prime);
+        cache.insert("Product", &params, product_sub_circuit());
+
+        let path = env::temp_dir().join(format!("cache_{}.bin", generate_u32()));
+        cache.save(&path).unwrap();
+        let mut loaded = CompilationCache::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        assert!(loaded.get("Product", &[BigInt::from(3)]).is_none());
+        let mut circuit = ArithmeticCircuit::new();
+        let signals = loaded
+            .get("Product", &params)
+            .unwrap()
+            .graft(&mut circuit)
+            .unwrap();
+        assert_eq!(circuit.gate_count(), 1);
+        let ids: HashSet<u32> = signals.values().flat_map(Signal::get_ids).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.iter().all(|id| circuit.contains_var(id)));
+
+        // The cache is only valid in the field it was built in
+        loaded.set_prime(&prime);
+        assert_eq!(loaded.len(), 1);
+        loaded.set_prime(&BigInt::from(17));
+        assert!(loaded.is_empty());
+    }
+}
@@ -30,6 +30,9 @@ pub struct Input {
     pub parallel_simplification_flag: bool,
     pub flag_old_heuristics: bool,
     pub inspect_constraints_flag: bool,
+    pub fold_constants_flag: bool,
+    pub cse_flag: bool,
+    pub dead_signal_elimination_flag: bool,
     pub no_rounds: usize,
     pub flag_verbose: bool,
     pub prime: String,
@@ -99,6 +102,9 @@ impl Input {
             reduced_simplification_flag: o_style == SimplificationStyle::O1,
             parallel_simplification_flag: input_processing::get_parallel_simplification(&matches),
             inspect_constraints_flag: input_processing::get_inspect_constraints(&matches),
+            fold_constants_flag: input_processing::get_fold_constants(&matches),
+            cse_flag: input_processing::get_cse(&matches),
+            dead_signal_elimination_flag: input_processing::get_dead_signal_elimination(&matches),
             flag_old_heuristics: input_processing::get_flag_old_heuristics(&matches),
             flag_verbose: input_processing::get_flag_verbose(&matches), 
             prime: input_processing::get_prime(&matches)?,
@@ -197,6 +203,15 @@ impl Input {
     pub fn inspect_constraints_flag(&self) -> bool {
         self.inspect_constraints_flag
     }
+    pub fn fold_constants_flag(&self) -> bool {
+        self.fold_constants_flag
+    }
+    pub fn cse_flag(&self) -> bool {
+        self.cse_flag
+    }
+    pub fn dead_signal_elimination_flag(&self) -> bool {
+        self.dead_signal_elimination_flag
+    }
     pub fn flag_verbose(&self) -> bool {
         self.flag_verbose
     }
@@ -310,6 +325,18 @@ pub mod input_processing {
         matches.is_present("inspect_constraints")
     }
 
+    pub fn get_fold_constants(matches: &ArgMatches) -> bool {
+        matches.is_present("fold_constants")
+    }
+
+    pub fn get_cse(matches: &ArgMatches) -> bool {
+        matches.is_present("cse")
+    }
+
+    pub fn get_dead_signal_elimination(matches: &ArgMatches) -> bool {
+        matches.is_present("dead_signal_elimination")
+    }
+
     pub fn get_flag_verbose(matches: &ArgMatches) -> bool {
         matches.is_present("flag_verbose")
     }
@@ -417,6 +444,27 @@ pub mod input_processing {
                     .display_order(801)
                     .help("Does an additional check over the constraints produced"),
             )
+            .arg(
+                Arg::with_name("fold_constants")
+                    .long("fold_constants")
+                    .takes_value(false)
+                    .display_order(802)
+                    .help("Evaluates the gates with constant inputs at compile time"),
+            )
+            .arg(
+                Arg::with_name("cse")
+                    .long("cse")
+                    .takes_value(false)
+                    .display_order(803)
+                    .help("Merges the gates computing the same operation on the same inputs"),
+            )
+            .arg(
+                Arg::with_name("dead_signal_elimination")
+                    .long("dead_signal_elimination")
+                    .takes_value(false)
+                    .display_order(804)
+                    .help("Removes the gates and signals not contributing to the outputs"),
+            )
             .arg(
                 Arg::with_name("print_json_sub")
                     .long("simplification_substitution")
@@ -1,13 +1,24 @@
 use super::{compilation::VERSION, input::Input};
-use circom_parser::run_parser;
-use circom_program_structure::{error_definition::Report, program_archive::ProgramArchive};
+use circom_parser::{lang::ParseAstParser, run_parser};
+use circom_program_structure::{
+    ast::Definition,
+    error_definition::Report,
+    file_definition::{FileID, FileLibrary},
+    program_archive::ProgramArchive,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 
 pub fn parse_project(input_info: &Input) -> Result<ProgramArchive, ()> {
     let initial_file = input_info.input_file().to_string();
+    parse_file(initial_file, input_info.get_link_libraries().to_vec())
+}
+
+pub fn parse_file(initial_file: String, link_libraries: Vec<PathBuf>) -> Result<ProgramArchive, ()> {
     let result_program_archive = run_parser(
         initial_file,
         VERSION,
-        input_info.get_link_libraries().to_vec(),
+        link_libraries,
     );
     match result_program_archive {
         Result::Err((file_library, report_collection)) => {
@@ -20,3 +31,158 @@ pub fn parse_project(input_info: &Input) -> Result<ProgramArchive, ()> {
         }
     }
 }
+
+/// Parses a program from in-memory sources, for targets without a filesystem like WebAssembly.
+/// `files` is a virtual file library mapping paths to sources, against which includes are resolved relative to the
+/// including file. The syntactic sugar removal of the circom parser is private, so anonymous components and tuples
+/// aren't desugared, they're handled by the compiler itself.
+pub fn parse_sources(
+    files: &HashMap<String, String>,
+    main_file: &str,
+) -> Result<ProgramArchive, ()> {
+    let files: HashMap<String, &String> = files
+        .iter()
+        .map(|(path, source)| (normalize_path(Path::new(path)), source))
+        .collect();
+
+    let mut file_library = FileLibrary::new();
+    let mut definitions: HashMap<FileID, Vec<Definition>> = HashMap::new();
+    let mut main_component = None;
+    let mut custom_gates = false;
+    let mut pending = vec![normalize_path(Path::new(main_file))];
+    let mut parsed = HashSet::new();
+
+    while let Some(path) = pending.pop() {
+        if !parsed.insert(path.clone()) {
+            continue;
+        }
+        let source = files.get(&path).ok_or(())?;
+        let file_id = file_library.add_file(path.clone(), source.to_string());
+
+        let mut errors = Vec::new();
+        let ast = ParseAstParser::new()
+            .parse(file_id, &mut errors, &strip_comments(source))
+            .map_err(|_| ())?;
+        if !errors.is_empty() {
+            Report::print_reports(&errors, &file_library);
+            return Err(());
+        }
+
+        if let Some(main) = ast.main_component {
+            // A program has a single main component
+            if main_component.replace((file_id, main)).is_some() {
+                return Err(());
+            }
+            custom_gates = ast.custom_gates;
+        }
+        let directory = Path::new(&path).parent().unwrap_or(Path::new(""));
+        pending.extend(
+            ast.includes
+                .iter()
+                .map(|include| normalize_path(&directory.join(include))),
+        );
+        definitions.insert(file_id, ast.definitions);
+    }
+
+    let (main_file_id, main_component) = main_component.ok_or(())?;
+    match ProgramArchive::new(
+        file_library,
+        main_file_id,
+        &main_component,
+        &definitions,
+        custom_gates,
+    ) {
+        Err((file_library, report_collection)) => {
+            Report::print_reports(&report_collection, &file_library);
+            Err(())
+        }
+        Ok(program_archive) => Ok(program_archive),
+    }
+}
+
+/// Normalizes a path of the virtual file library, resolving its `.` and `..` components.
+fn normalize_path(path: &Path) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                components.pop();
+            }
+            Component::Normal(name) => components.push(name.to_str().unwrap_or_default()),
+            Component::RootDir | Component::Prefix(_) => components.clear(),
+        }
+    }
+
+    components.join("/")
+}
+
+/// Replaces the comments of a source with spaces, keeping line breaks so that locations are unchanged.
+/// The circom grammar doesn't handle comments, which its parser removes before parsing.
+fn strip_comments(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let mut stripped = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    let mut in_string = false;
+
+    while index < bytes.len() {
+        let byte = bytes[index];
+        let next = bytes.get(index + 1).copied();
+
+        if in_string {
+            // Escaped characters are kept along with their backslash
+            if byte == b'\\' && next.is_some() {
+                stripped.push(byte);
+                index += 1;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            stripped.push(bytes[index]);
+            index += 1;
+        } else if byte == b'/' && next == Some(b'/') {
+            while index < bytes.len() && bytes[index] != b'\n' {
+                stripped.push(b' ');
+                index += 1;
+            }
+        } else if byte == b'/' && next == Some(b'*') {
+            let end = source[index + 2..]
+                .find("*/")
+                .map_or(bytes.len(), |offset| index + 2 + offset + 2);
+            stripped.extend(
+                bytes[index..end]
+                    .iter()
+                    .map(|&byte| if byte == b'\n' { b'\n' } else { b' ' }),
+            );
+            index = end;
+        } else {
+            in_string = byte == b'"';
+            stripped.push(byte);
+            index += 1;
+        }
+    }
+
+    // Only whole comments are replaced, so the remaining bytes are still valid UTF-8
+    String::from_utf8(stripped).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_comments_keeps_locations() {
+        let source = "a // b\n/* c\nd */ e \"// f\"";
+        let stripped = strip_comments(source);
+
+        assert_eq!(stripped.len(), source.len());
+        assert_eq!(stripped, "a     \n    \n     e \"// f\"");
+    }
+
+    #[test]
+    fn normalize_path_resolves_relative_components() {
+        assert_eq!(
+            normalize_path(Path::new("./lib/../circuits/add.circom")),
+            "circuits/add.circom"
+        );
+    }
+}
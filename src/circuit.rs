@@ -2,26 +2,56 @@
 //!
 //! This module defines the data structures used to represent the arithmetic circuit.
 
-use crate::{program::ProgramError, runtime::generate_u32};
+use crate::{
+    export::{
+        bristol, dot,
+        plonk::{self, CustomGateRegistry, PlonkGate},
+        r1cs::R1CSFile,
+    },
+    process::{execute_op, modulo},
+    program::ProgramError,
+    runtime::generate_u32,
+};
 use circom_program_structure::ast::ExpressionInfixOpcode;
 use log::debug;
 use mpz_circuits::GateType;
+use num_bigint_dig::{BigInt, Sign};
+use rand::{thread_rng, Rng};
 use regex::Captures;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
+    fmt,
+    hash::{Hash, Hasher},
+};
 use thiserror::Error;
 
+/// Version of the circuit JSON representation, increased on incompatible schema changes.
+pub const CIRCUIT_JSON_VERSION: u32 = 3;
+
+/// Default number of bits of the operands of comparisons lowered by `ArithmeticCircuit::lower_comparisons`,
+/// which is the largest size supported by the circomlib comparators.
+pub const DEFAULT_COMPARISON_BITS: u32 = 252;
+
+/// Identifier of a gate in the circuit.
+pub type GateId = u32;
+
 /// Types of gates that can be used in an arithmetic circuit.
-#[derive(Debug, Serialize, Deserialize)]
+/// `ACMul` is a multiplication by a constant, which is linear, unlike the `AMul` product of two signals.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AGateType {
     AAdd,
+    ACMul,
     ADiv,
     AEq,
     AGEq,
     AGt,
+    AInv,
     ALEq,
     ALt,
     AMul,
+    ANeg,
     ANeq,
     ANone,
     ASub,
@@ -45,6 +75,93 @@ impl From<&ExpressionInfixOpcode> for AGateType {
     }
 }
 
+impl AGateType {
+    /// Returns the infix operation computed by the gate, if any.
+    pub fn to_infix_opcode(&self) -> Option<ExpressionInfixOpcode> {
+        match self {
+            AGateType::AAdd => Some(ExpressionInfixOpcode::Add),
+            AGateType::ACMul => Some(ExpressionInfixOpcode::Mul),
+            AGateType::ADiv => Some(ExpressionInfixOpcode::Div),
+            AGateType::AEq => Some(ExpressionInfixOpcode::Eq),
+            AGateType::AGEq => Some(ExpressionInfixOpcode::GreaterEq),
+            AGateType::AGt => Some(ExpressionInfixOpcode::Greater),
+            AGateType::AInv => None,
+            AGateType::ALEq => Some(ExpressionInfixOpcode::LesserEq),
+            AGateType::ALt => Some(ExpressionInfixOpcode::Lesser),
+            AGateType::AMul => Some(ExpressionInfixOpcode::Mul),
+            AGateType::ANeg => None,
+            AGateType::ANeq => Some(ExpressionInfixOpcode::NotEq),
+            AGateType::ANone => None,
+            AGateType::ASub => Some(ExpressionInfixOpcode::Sub),
+        }
+    }
+
+    /// Checks if gates of this type have a single input.
+    pub fn is_unary(&self) -> bool {
+        matches!(self, AGateType::AInv | AGateType::ANeg)
+    }
+
+    /// Computes the output of a gate of this type, or `None` for `ANone` gates, which don't compute any operation.
+    /// Unary gates ignore the right-hand input, which binary gates require.
+    pub fn evaluate(
+        &self,
+        lhs: &BigInt,
+        rhs: Option<&BigInt>,
+        prime: &BigInt,
+    ) -> Result<Option<BigInt>, CircuitError> {
+        let evaluation_error = |e: ProgramError| CircuitError::GateEvaluationError(e.to_string());
+
+        let value = match self {
+            AGateType::ANeg => modulo(&-lhs, prime),
+            AGateType::AInv => {
+                execute_op(&BigInt::from(1u32), lhs, &ExpressionInfixOpcode::Div, prime)
+                    .map_err(evaluation_error)?
+            }
+            _ => {
+                let op = match self.to_infix_opcode() {
+                    Some(op) => op,
+                    None => return Ok(None),
+                };
+                let rhs = rhs.ok_or_else(|| {
+                    CircuitError::GateEvaluationError(format!(
+                        "{:?} without right-hand input",
+                        self
+                    ))
+                })?;
+                execute_op(lhs, rhs, &op, prime).map_err(evaluation_error)?
+            }
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Checks if the gate output doesn't depend on the order of its inputs.
+    pub fn is_commutative(&self) -> bool {
+        matches!(
+            self,
+            AGateType::AAdd | AGateType::ACMul | AGateType::AEq | AGateType::AMul | AGateType::ANeq
+        )
+    }
+}
+
+/// Structural identity of a gate: its type and input nodes.
+/// Inputs of commutative gates are sorted, so that `a + b` and `b + a` share the same key.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct GateKey(AGateType, u32, Option<u32>);
+
+impl GateKey {
+    fn new(gate: &ArithmeticGate) -> Self {
+        let (lhs, rhs) = match gate.rh_input {
+            Some(rh_input) if gate.gate_type.is_commutative() && rh_input < gate.lh_input => {
+                (rh_input, Some(gate.lh_input))
+            }
+            _ => (gate.lh_input, gate.rh_input),
+        };
+
+        GateKey(gate.gate_type.clone(), lhs, rhs)
+    }
+}
+
 /// Represents a node in the circuit, with an identifier and a set of signals that it is connected to.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
@@ -66,6 +183,11 @@ impl Node {
         self.signals.extend(signals);
     }
 
+    /// Gets the id of the node.
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
     /// Gets the signals of the node.
     pub fn get_signals(&self) -> Vec<u32> {
         self.signals.clone()
@@ -87,22 +209,34 @@ impl Node {
     /// Checks if the Node contains an output signal
     pub fn has_output(&self, circuit: &ArithmeticCircuit) -> bool {
         circuit.gates.iter().any(|gate| gate.output == self.id)
+            || circuit
+                .custom_gates
+                .iter()
+                .any(|gate| gate.output == self.id)
     }
 }
 
 /// Represents a circuit gate, with a left-hand input, right-hand input, and output node identifiers.
+/// Unary gates have no right-hand input.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ArithmeticGate {
-    id: u32,
+    id: GateId,
+    #[serde(rename = "type")]
     gate_type: AGateType,
     lh_input: u32,
-    rh_input: u32,
+    rh_input: Option<u32>,
     output: u32,
 }
 
 impl ArithmeticGate {
     /// Creates a new gate.
-    pub fn new(id: u32, gate_type: AGateType, lh_input: u32, rh_input: u32, output: u32) -> Self {
+    pub fn new(
+        id: GateId,
+        gate_type: AGateType,
+        lh_input: u32,
+        rh_input: Option<u32>,
+        output: u32,
+    ) -> Self {
         Self {
             id,
             gate_type,
@@ -111,14 +245,170 @@ impl ArithmeticGate {
             output,
         }
     }
+
+    /// Gets the id of the gate.
+    pub fn get_id(&self) -> GateId {
+        self.id
+    }
+
+    /// Gets the type of the gate.
+    pub fn get_gate_type(&self) -> &AGateType {
+        &self.gate_type
+    }
+
+    /// Gets the id of the left-hand input node.
+    pub fn get_lh_input(&self) -> u32 {
+        self.lh_input
+    }
+
+    /// Gets the id of the right-hand input node, if the gate isn't unary.
+    pub fn get_rh_input(&self) -> Option<u32> {
+        self.rh_input
+    }
+
+    /// Gets the ids of the input nodes, from the left-hand one.
+    pub fn get_inputs(&self) -> Vec<u32> {
+        [self.lh_input].into_iter().chain(self.rh_input).collect()
+    }
+
+    /// Gets the id of the output node.
+    pub fn get_output(&self) -> u32 {
+        self.output
+    }
+}
+
+/// Represents a gate of a type registered by the user, with any number of input nodes and an output node.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CustomGate {
+    name: String,
+    inputs: Vec<u32>,
+    output: u32,
+}
+
+impl CustomGate {
+    /// Gets the name of the gate type.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the ids of the input nodes.
+    pub fn get_inputs(&self) -> &[u32] {
+        &self.inputs
+    }
+
+    /// Gets the id of the output node.
+    pub fn get_output(&self) -> u32 {
+        self.output
+    }
+}
+
+/// Source location of the operation a gate was built from, for source level debugging.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateMeta {
+    pub source_file: String,
+    pub line: u32,
+    pub col: u32,
+    pub template_name: String,
+}
+
+/// Warning about a signal that is likely a mistake in the circom program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintWarning {
+    pub signal_id: u32,
+    pub name: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({}): {}", name, self.signal_id, self.message),
+            None => write!(f, "signal {}: {}", self.signal_id, self.message),
+        }
+    }
+}
+
+/// Size summary of a circuit.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CircuitStats {
+    /// Number of non constant signals.
+    pub signal_count: usize,
+    /// Number of constant signals.
+    pub constant_count: usize,
+    /// Number of gates.
+    pub gate_count: usize,
+    /// Number of gates of each type.
+    pub gates_by_type: HashMap<AGateType, usize>,
+    /// Number of connections between signals, each node of `n` signals holding `n - 1` of them.
+    pub connection_count: usize,
+}
+
+impl fmt::Display for CircuitStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<12} {:>10}", "Signals", self.signal_count)?;
+        writeln!(f, "{:<12} {:>10}", "Constants", self.constant_count)?;
+        writeln!(f, "{:<12} {:>10}", "Connections", self.connection_count)?;
+        writeln!(f, "{:<12} {:>10}", "Gates", self.gate_count)?;
+
+        // Gate types are listed by name, so the summary is stable across runs
+        let mut gates_by_type: Vec<(String, usize)> = self
+            .gates_by_type
+            .iter()
+            .map(|(gate_type, &count)| (format!("{:?}", gate_type), count))
+            .collect();
+        gates_by_type.sort();
+        for (gate_type, count) in gates_by_type {
+            writeln!(f, "  {:<10} {:>10}", gate_type, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Operation building a circuit, recorded to replay template instances in other circuits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum CircuitOp {
+    AddSignal(u32),
+    AddConst(BigInt, u32),
+    AddGate(AGateType, u32, Option<u32>, u32),
+    AddCustomGate(String, Vec<u32>, u32),
+    AddConnection(u32, u32),
+    AddConstraint(u32, u32),
+    RegisterSignalName(u32, String),
+    SetHintMode(bool),
+    MergeSignals(u32, u32),
 }
 
-/// Represents an arithmetic circuit, with a set of variables and gates.
+/// Represents an arithmetic circuit, with a set of variables, gates and equality constraints between signals.
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ArithmeticCircuit {
-    vars: HashMap<u32, Option<u32>>,
+    vars: HashMap<u32, Option<BigInt>>,
     nodes: Vec<Node>,
     gates: Vec<ArithmeticGate>,
+    constraints: Vec<(u32, u32)>,
+    inputs: Vec<u32>,
+    outputs: Vec<u32>,
+    custom_gate_types: HashMap<String, usize>,
+    custom_gates: Vec<CustomGate>,
+    #[serde(default)]
+    signal_names: HashMap<u32, String>,
+    #[serde(default)]
+    gate_meta: HashMap<GateId, GateMeta>,
+    #[serde(default)]
+    hint_gates: HashSet<GateId>,
+    #[serde(skip)]
+    hint_mode: bool,
+    #[serde(skip)]
+    assigned_signals: HashSet<u32>,
+    #[serde(skip)]
+    constant_ids: HashMap<BigInt, u32>,
+    #[serde(skip)]
+    recordings: Vec<Vec<CircuitOp>>,
+    /// Gates reading each node, as their id and output node. It's rebuilt on the next cycle check after being reset.
+    #[serde(skip)]
+    gate_readers: Option<HashMap<u32, Vec<(GateId, u32)>>>,
+    #[serde(skip)]
+    next_gate_id: Option<GateId>,
 }
 
 impl ArithmeticCircuit {
@@ -128,6 +418,20 @@ impl ArithmeticCircuit {
             vars: HashMap::new(),
             nodes: Vec::new(),
             gates: Vec::new(),
+            constraints: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            custom_gate_types: HashMap::new(),
+            custom_gates: Vec::new(),
+            signal_names: HashMap::new(),
+            gate_meta: HashMap::new(),
+            hint_gates: HashSet::new(),
+            hint_mode: false,
+            assigned_signals: HashSet::new(),
+            constant_ids: HashMap::new(),
+            recordings: Vec::new(),
+            gate_readers: None,
+            next_gate_id: None,
         }
     }
 
@@ -144,36 +448,56 @@ impl ArithmeticCircuit {
         debug!("New {:?}", node);
 
         self.nodes.push(node);
+        self.record(CircuitOp::AddSignal(id));
         Ok(())
     }
 
-    /// Adds a new constant variable to the circuit.
-    pub fn add_const(&mut self, value: u32) -> Result<(), CircuitError> {
-        // Ignore if the constant is already declared
-        if self.contains_var(&value) {
-            return Ok(());
+    /// Adds a new constant variable to the circuit, returning the id of the signal holding it.
+    pub fn add_const(&mut self, value: BigInt) -> Result<u32, CircuitError> {
+        // Reuse the constant if it's already declared and hasn't been removed since
+        if let Some(&id) = self.constant_ids.get(&value) {
+            if matches!(self.vars.get(&id), Some(Some(var)) if *var == value) {
+                self.record(CircuitOp::AddConst(value, id));
+                return Ok(id);
+            }
+        }
+
+        let mut id = generate_u32();
+        while self.contains_var(&id) {
+            id = generate_u32();
         }
-        self.vars.insert(value, Some(value));
+        self.vars.insert(id, Some(value.clone()));
+        self.constant_ids.insert(value.clone(), id);
 
         // Create a new node for the constant
-        let node = Node::new(value);
+        let node = Node::new(id);
         debug!("New {:?}", node);
 
         self.nodes.push(node);
-        Ok(())
+        self.record(CircuitOp::AddConst(value, id));
+        Ok(id)
     }
 
-    /// Adds a new gate to the circuit.
+    /// Adds a new gate to the circuit, returning its id. Unary gates take no right-hand input, which binary gates
+    /// require.
     pub fn add_gate(
         &mut self,
         gate_type: AGateType,
         lhs_id: u32,
-        rhs_id: u32,
+        rhs_id: Option<u32>,
         output_id: u32,
-    ) -> Result<(), CircuitError> {
+    ) -> Result<GateId, CircuitError> {
+        if gate_type.is_unary() != rhs_id.is_none() {
+            return Err(CircuitError::InvalidGateInputs(format!(
+                "{:?} with {} inputs",
+                gate_type,
+                1 + usize::from(rhs_id.is_some())
+            )));
+        }
+
         // Check that the inputs are declared
         if !self.contains_var(&lhs_id)
-            || !self.contains_var(&rhs_id)
+            || !rhs_id.iter().all(|rhs_id| self.contains_var(rhs_id))
             || !self.contains_var(&output_id)
         {
             return Err(CircuitError::VariableNotDeclared);
@@ -181,20 +505,101 @@ impl ArithmeticCircuit {
 
         // Get the signal nodes
         let lhs_node = self.get_signal_node(lhs_id)?;
-        let rhs_node = self.get_signal_node(rhs_id)?;
+        let rhs_node = rhs_id.map(|id| self.get_signal_node(id)).transpose()?;
         let output_node = self.get_signal_node(output_id)?;
+        let input_nodes: Vec<u32> = [lhs_node.id]
+            .into_iter()
+            .chain(rhs_node.as_ref().map(|node| node.id))
+            .collect();
+
+        // The output can't feed the inputs of the gate
+        if input_nodes.contains(&output_node.id)
+            || self.find_gate_path(output_node.id, &input_nodes).is_some()
+        {
+            return Err(CircuitError::CircuitCycleDetected {
+                gate_id: self.next_gate_id(),
+            });
+        }
 
         // Create gate
+        let gate_id = self.next_gate_id();
         let gate = ArithmeticGate::new(
-            self.gate_count(),
-            gate_type,
+            gate_id,
+            gate_type.clone(),
             lhs_node.id,
-            rhs_node.id,
+            rhs_node.map(|node| node.id),
             output_node.id,
         );
         debug!("New {:?} ", gate);
 
+        if let Some(readers) = self.gate_readers.as_mut() {
+            index_gate_reader(readers, &gate);
+        }
+        self.next_gate_id = Some(gate_id + 1);
         self.gates.push(gate);
+        if self.hint_mode {
+            self.hint_gates.insert(gate_id);
+        }
+        self.record(CircuitOp::AddGate(gate_type, lhs_id, rhs_id, output_id));
+        Ok(gate_id)
+    }
+
+    /// Registers a custom gate type with the number of inputs of its gates.
+    pub fn register_custom_gate(&mut self, name: &str, arity: usize) -> Result<(), CircuitError> {
+        if self.custom_gate_types.contains_key(name) {
+            return Err(CircuitError::CustomGateAlreadyRegistered(name.to_string()));
+        }
+
+        self.custom_gate_types.insert(name.to_string(), arity);
+        Ok(())
+    }
+
+    /// Adds a gate of a registered custom type to the circuit.
+    pub fn add_custom_gate(
+        &mut self,
+        name: &str,
+        inputs: &[u32],
+        output: u32,
+    ) -> Result<(), CircuitError> {
+        let arity = *self
+            .custom_gate_types
+            .get(name)
+            .ok_or_else(|| CircuitError::CustomGateNotRegistered(name.to_string()))?;
+        if inputs.len() != arity {
+            return Err(CircuitError::CustomGateArityMismatch(format!(
+                "{} expects {} inputs, got {}",
+                name,
+                arity,
+                inputs.len()
+            )));
+        }
+
+        // Check that the inputs and the output are declared
+        if !inputs
+            .iter()
+            .chain([&output])
+            .all(|id| self.contains_var(id))
+        {
+            return Err(CircuitError::VariableNotDeclared);
+        }
+
+        let input_nodes = inputs
+            .iter()
+            .map(|&id| Ok(self.get_signal_node(id)?.id))
+            .collect::<Result<Vec<u32>, CircuitError>>()?;
+        let gate = CustomGate {
+            name: name.to_string(),
+            inputs: input_nodes,
+            output: self.get_signal_node(output)?.id,
+        };
+        debug!("New {:?} ", gate);
+
+        self.custom_gates.push(gate);
+        self.record(CircuitOp::AddCustomGate(
+            name.to_string(),
+            inputs.to_vec(),
+            output,
+        ));
         Ok(())
     }
 
@@ -212,6 +617,7 @@ impl ArithmeticCircuit {
 
         // If both endpoints are in the same node, no action is needed
         if node_a.id == node_b.id {
+            self.record(CircuitOp::AddConnection(a, b));
             return Ok(());
         }
 
@@ -220,6 +626,14 @@ impl ArithmeticCircuit {
             return Err(CircuitError::CannotMergeOutputNodes);
         }
 
+        // Merging nodes linked by a path of gates would make the path a cycle
+        if let Some(gate_id) = self
+            .find_gate_path(node_a.id, &[node_b.id])
+            .or_else(|| self.find_gate_path(node_b.id, &[node_a.id]))
+        {
+            return Err(CircuitError::CircuitCycleDetected { gate_id });
+        }
+
         // Merge the nodes
         let merged_node = node_a.merge(&node_b);
 
@@ -228,102 +642,2046 @@ impl ArithmeticCircuit {
             if gate.lh_input == node_a.id || gate.lh_input == node_b.id {
                 gate.lh_input = merged_node.id;
             }
-            if gate.rh_input == node_a.id || gate.rh_input == node_b.id {
-                gate.rh_input = merged_node.id;
+            if gate.rh_input == Some(node_a.id) || gate.rh_input == Some(node_b.id) {
+                gate.rh_input = Some(merged_node.id);
+            }
+            if gate.output == node_a.id || gate.output == node_b.id {
+                gate.output = merged_node.id;
+            }
+        });
+        self.custom_gates.iter_mut().for_each(|gate| {
+            for input in gate.inputs.iter_mut() {
+                if *input == node_a.id || *input == node_b.id {
+                    *input = merged_node.id;
+                }
             }
             if gate.output == node_a.id || gate.output == node_b.id {
                 gate.output = merged_node.id;
             }
         });
+        self.gate_readers = None;
 
         // Remove the old nodes and add the new merged node
         self.nodes
             .retain(|node| node.id != node_a.id && node.id != node_b.id);
         self.nodes.push(merged_node);
 
+        self.record(CircuitOp::AddConnection(a, b));
         Ok(())
     }
 
-    /// Returns the node containing the given signal.
-    fn get_signal_node(&self, signal_id: u32) -> Result<Node, CircuitError> {
-        for node in &self.nodes {
-            if node.signals.contains(&signal_id) {
-                return Ok(node.clone());
+    /// Merges the `remove` signal into the `keep` signal, which takes its place in the gates, constraints, inputs and
+    /// outputs of the circuit. The nodes of both signals are merged like with `add_connection`, which fails if a gate
+    /// path links them, as merging them would make the path a cycle. A constant value of `remove` is moved to `keep`.
+    pub fn merge_signals(&mut self, keep: u32, remove: u32) -> Result<(), CircuitError> {
+        if keep == remove {
+            return Ok(());
+        }
+
+        let keep_value = self
+            .vars
+            .get(&keep)
+            .ok_or(CircuitError::VariableNotDeclared)?;
+        let remove_value = self
+            .vars
+            .get(&remove)
+            .ok_or(CircuitError::VariableNotDeclared)?;
+        if matches!((keep_value, remove_value), (Some(a), Some(b)) if a != b) {
+            return Err(CircuitError::ConstantValueAlreadySet);
+        }
+        let value = remove_value.clone();
+
+        self.add_connection(keep, remove)?;
+
+        // Gates refer to nodes, so only the signal lists need to be updated
+        for node in self.nodes.iter_mut() {
+            node.signals.retain(|&signal| signal != remove);
+        }
+        self.vars.remove(&remove);
+        if let Some(value) = value {
+            self.vars.insert(keep, Some(value.clone()));
+            self.constant_ids.insert(value, keep);
+        }
+
+        let replace = |signal: &mut u32| {
+            if *signal == remove {
+                *signal = keep;
             }
+        };
+        for (lhs, rhs) in self.constraints.iter_mut() {
+            replace(lhs);
+            replace(rhs);
+        }
+        for signals in [&mut self.inputs, &mut self.outputs] {
+            signals.iter_mut().for_each(replace);
+            let mut seen = HashSet::new();
+            signals.retain(|signal| seen.insert(*signal));
         }
 
-        Err(CircuitError::NodeNotFound)
+        if let Some(name) = self.signal_names.remove(&remove) {
+            self.signal_names.entry(keep).or_insert(name);
+        }
+        if self.assigned_signals.remove(&remove) {
+            self.assigned_signals.insert(keep);
+        }
+
+        self.record(CircuitOp::MergeSignals(keep, remove));
+        Ok(())
     }
 
-    /// Checks if the variable exists
-    pub fn contains_var(&self, var: &u32) -> bool {
-        self.vars.contains_key(var)
+    /// Adds an equality constraint (`lhs === rhs`) between two signals of the circuit.
+    pub fn add_constraint(&mut self, lhs_id: u32, rhs_id: u32) -> Result<(), CircuitError> {
+        // Check that both signals are declared
+        if !self.contains_var(&lhs_id) || !self.contains_var(&rhs_id) {
+            return Err(CircuitError::VariableNotDeclared);
+        }
+
+        debug!("New constraint {} === {}", lhs_id, rhs_id);
+        self.constraints.push((lhs_id, rhs_id));
+        self.record(CircuitOp::AddConstraint(lhs_id, rhs_id));
+        Ok(())
     }
 
-    /// Returns the number of gates in the circuit.
-    pub fn gate_count(&self) -> u32 {
-        self.gates.len() as u32
+    /// Sets whether the gates added next compute witness hints, assigned with `<--`.
+    /// Hint gates are evaluated like any other gate, but they don't constrain their output in proof system exports.
+    pub fn set_hint_mode(&mut self, hint_mode: bool) {
+        self.hint_mode = hint_mode;
+        self.record(CircuitOp::SetHintMode(hint_mode));
     }
-}
 
-#[allow(dead_code)]
-/// Represents a gate in its raw, unchecked form, used during parsing.
-pub struct UncheckedGate {
-    xref: usize,
-    yref: Option<usize>,
-    zref: usize,
-    gate_type: GateType,
-}
+    /// Records that a signal is assigned by the program, returning `false` if it already was.
+    /// Circom only allows a single assignment per signal, which is checked while the circuit is built.
+    pub fn assign_signal(&mut self, signal_id: u32) -> bool {
+        self.assigned_signals.insert(signal_id)
+    }
 
-impl UncheckedGate {
-    #[allow(dead_code)]
-    pub fn parse(captures: Captures) -> Result<Self, CircuitError> {
-        let xref: usize = captures.name("xref").unwrap().as_str().parse()?;
-        let yref: Option<usize> = captures
-            .name("yref")
-            .map(|yref| yref.as_str().parse())
-            .transpose()?;
-        let zref: usize = captures.name("zref").unwrap().as_str().parse()?;
-        let gate_type = captures.name("gate").unwrap().as_str();
+    /// Checks if a gate computes a witness hint.
+    pub fn is_hint_gate(&self, gate_id: GateId) -> bool {
+        self.hint_gates.contains(&gate_id)
+    }
 
-        let gate_type = match gate_type {
-            "XOR" => GateType::Xor,
-            "AND" => GateType::And,
-            "INV" => GateType::Inv,
-            _ => return Err(CircuitError::UnsupportedGateType(gate_type.to_string())),
+    /// Starts recording the operations building the circuit, until the matching `stop_recording` call.
+    /// Recordings can be nested, operations are recorded in all of them.
+    pub fn start_recording(&mut self) {
+        self.recordings.push(Vec::new());
+    }
+
+    /// Stops the innermost recording, returning its operations.
+    pub fn stop_recording(&mut self) -> Vec<CircuitOp> {
+        self.recordings.pop().unwrap_or_default()
+    }
+
+    /// Replays recorded operations, returning the mapping from the recorded signal ids to the new ones.
+    /// Signals get fresh ids and constants reuse the ones of the circuit.
+    /// Operations can only refer to signals declared by earlier operations.
+    pub fn replay(&mut self, ops: &[CircuitOp]) -> Result<HashMap<u32, u32>, CircuitError> {
+        let mut ids: HashMap<u32, u32> = HashMap::new();
+        let map = |ids: &HashMap<u32, u32>, id: &u32| {
+            ids.get(id)
+                .copied()
+                .ok_or(CircuitError::VariableNotDeclared)
         };
 
-        Ok(Self {
-            xref,
-            yref,
-            zref,
-            gate_type,
-        })
+        for op in ops {
+            match op {
+                CircuitOp::AddSignal(id) => {
+                    let mut new_id = generate_u32();
+                    while self.contains_var(&new_id) {
+                        new_id = generate_u32();
+                    }
+                    self.add_signal(new_id)?;
+                    ids.insert(*id, new_id);
+                }
+                CircuitOp::AddConst(value, id) => {
+                    let new_id = self.add_const(value.clone())?;
+                    ids.insert(*id, new_id);
+                }
+                CircuitOp::AddGate(gate_type, lhs_id, rhs_id, output_id) => {
+                    self.add_gate(
+                        gate_type.clone(),
+                        map(&ids, lhs_id)?,
+                        rhs_id.as_ref().map(|id| map(&ids, id)).transpose()?,
+                        map(&ids, output_id)?,
+                    )?;
+                }
+                CircuitOp::AddCustomGate(name, inputs, output) => {
+                    let inputs = inputs
+                        .iter()
+                        .map(|id| map(&ids, id))
+                        .collect::<Result<Vec<u32>, CircuitError>>()?;
+                    self.add_custom_gate(name, &inputs, map(&ids, output)?)?
+                }
+                CircuitOp::AddConnection(a, b) => {
+                    self.add_connection(map(&ids, a)?, map(&ids, b)?)?
+                }
+                CircuitOp::AddConstraint(lhs_id, rhs_id) => {
+                    self.add_constraint(map(&ids, lhs_id)?, map(&ids, rhs_id)?)?
+                }
+                CircuitOp::RegisterSignalName(id, name) => {
+                    self.register_signal_name(map(&ids, id)?, name)?
+                }
+                CircuitOp::SetHintMode(hint_mode) => self.set_hint_mode(*hint_mode),
+                CircuitOp::MergeSignals(keep, remove) => {
+                    self.merge_signals(map(&ids, keep)?, map(&ids, remove)?)?
+                }
+            }
+        }
+
+        Ok(ids)
     }
-}
 
-#[derive(Debug, Error)]
-pub enum CircuitError {
-    #[error("Cannot merge output nodes")]
-    CannotMergeOutputNodes,
-    #[error("Circuit variable already declared")]
-    CircuitVariableAlreadyDeclared,
-    #[error("Constant value already set for variable")]
-    ConstantValueAlreadySet,
-    #[error(transparent)]
-    IOError(#[from] std::io::Error),
-    #[error(transparent)]
-    ParseIntError(#[from] std::num::ParseIntError),
-    #[error("Node not found")]
-    NodeNotFound,
-    #[error("unsupported gate type: {0}")]
-    UnsupportedGateType(String),
-    #[error("Variable not declared")]
-    VariableNotDeclared,
-}
+    /// Records an operation in the active recordings.
+    fn record(&mut self, op: CircuitOp) {
+        for recording in self.recordings.iter_mut() {
+            recording.push(op.clone());
+        }
+    }
 
-impl From<CircuitError> for ProgramError {
-    fn from(e: CircuitError) -> Self {
-        ProgramError::CircuitError(e)
+    /// Marks a signal as an input of the circuit.
+    pub fn mark_input(&mut self, signal_id: u32) -> Result<(), CircuitError> {
+        if !self.contains_var(&signal_id) {
+            return Err(CircuitError::VariableNotDeclared);
+        }
+
+        // Inputs are kept in order, as they define the order of the input wires on export
+        if !self.inputs.contains(&signal_id) {
+            self.inputs.push(signal_id);
+        }
+        Ok(())
+    }
+
+    /// Marks a signal as an output of the circuit.
+    pub fn mark_output(&mut self, signal_id: u32) -> Result<(), CircuitError> {
+        if !self.contains_var(&signal_id) {
+            return Err(CircuitError::VariableNotDeclared);
+        }
+
+        // Outputs are kept in order, as they define the order of the output wires on export
+        if !self.outputs.contains(&signal_id) {
+            self.outputs.push(signal_id);
+        }
+        Ok(())
+    }
+
+    /// Registers the name of a signal in the circom program, used by diagnostics and exports.
+    pub fn register_signal_name(&mut self, signal_id: u32, name: &str) -> Result<(), CircuitError> {
+        if !self.contains_var(&signal_id) {
+            return Err(CircuitError::VariableNotDeclared);
+        }
+
+        self.signal_names.insert(signal_id, name.to_string());
+        self.record(CircuitOp::RegisterSignalName(signal_id, name.to_string()));
+        Ok(())
+    }
+
+    /// Returns the registered name of a signal, if any.
+    pub fn signal_name(&self, signal_id: u32) -> Option<&str> {
+        self.signal_names.get(&signal_id).map(String::as_str)
+    }
+
+    /// Attaches the source location of the operation a gate was built from.
+    pub fn annotate_gate(&mut self, gate_id: GateId, meta: GateMeta) -> Result<(), CircuitError> {
+        if !self.gates.iter().any(|gate| gate.id == gate_id) {
+            return Err(CircuitError::GateNotFound);
+        }
+
+        self.gate_meta.insert(gate_id, meta);
+        Ok(())
+    }
+
+    /// Returns the source location attached to a gate, if any.
+    pub fn gate_meta(&self, gate_id: GateId) -> Option<&GateMeta> {
+        self.gate_meta.get(&gate_id)
+    }
+
+    /// Returns the registered name of a signal, or `signal_{id}` if it has none.
+    pub fn signal_label(&self, signal_id: u32) -> String {
+        match self.signal_name(signal_id) {
+            Some(name) => name.to_string(),
+            None => format!("signal_{}", signal_id),
+        }
+    }
+
+    /// Returns the output signals that are neither driven by a gate nor connected to another signal or a constant.
+    pub fn check_outputs_constrained(&self) -> Result<Vec<u32>, CircuitError> {
+        let mut unconstrained = Vec::new();
+        for &signal in &self.outputs {
+            let node = self.get_signal_node(signal)?;
+            if node.signals.len() == 1 && !node.has_output(self) {
+                unconstrained.push(signal);
+            }
+        }
+
+        Ok(unconstrained)
+    }
+
+    /// Returns a warning for each input signal that isn't read by any gate, connected to another signal or constrained,
+    /// which usually means that the template declares an input it never uses.
+    pub fn lint_unused_inputs(&self) -> Vec<LintWarning> {
+        let read_nodes: HashSet<u32> = self
+            .gates
+            .iter()
+            .flat_map(|gate| gate.get_inputs())
+            .chain(
+                self.custom_gates
+                    .iter()
+                    .flat_map(|gate| gate.inputs.iter().copied()),
+            )
+            .collect();
+        let constrained: HashSet<u32> = self
+            .constraints
+            .iter()
+            .flat_map(|&(lhs, rhs)| [lhs, rhs])
+            .collect();
+
+        self.inputs
+            .iter()
+            .filter(|&&signal| !constrained.contains(&signal))
+            .filter(|&&signal| match self.get_signal_node(signal) {
+                Ok(node) => node.signals.len() == 1 && !read_nodes.contains(&node.id),
+                Err(_) => false,
+            })
+            .map(|&signal| LintWarning {
+                signal_id: signal,
+                name: self.signal_name(signal).map(str::to_string),
+                message: "input signal is never used".to_string(),
+            })
+            .collect()
+    }
+
+    /// Removes the gates and signals that don't contribute to any output of the circuit.
+    /// Input signals and signals involved in equality constraints are always kept.
+    /// If no output is marked, the circuit is left untouched.
+    /// Returns the number of removed signals and gates.
+    pub fn eliminate_dead_signals(&mut self) -> (usize, usize) {
+        if self.outputs.is_empty() {
+            return (0, 0);
+        }
+
+        let roots: HashSet<u32> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .chain(self.constraints.iter().flat_map(|(lhs, rhs)| [lhs, rhs]))
+            .copied()
+            .collect();
+        let mut live_nodes: HashSet<u32> = self
+            .nodes
+            .iter()
+            .filter(|node| node.signals.iter().any(|signal| roots.contains(signal)))
+            .map(|node| node.id)
+            .collect();
+
+        // Walk the gates backwards from the live nodes until no new node is reached
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for gate in &self.gates {
+                if live_nodes.contains(&gate.output) {
+                    for input in gate.get_inputs() {
+                        changed |= live_nodes.insert(input);
+                    }
+                }
+            }
+            for gate in &self.custom_gates {
+                if live_nodes.contains(&gate.output) {
+                    for &input in &gate.inputs {
+                        changed |= live_nodes.insert(input);
+                    }
+                }
+            }
+        }
+
+        let gate_count = self.gates.len();
+        self.gates.retain(|gate| live_nodes.contains(&gate.output));
+        self.gate_readers = None;
+        let live_gates: HashSet<GateId> = self.gates.iter().map(|gate| gate.id).collect();
+        self.gate_meta
+            .retain(|gate_id, _| live_gates.contains(gate_id));
+        self.hint_gates
+            .retain(|gate_id| live_gates.contains(gate_id));
+        self.custom_gates
+            .retain(|gate| live_nodes.contains(&gate.output));
+        let dead_gates = gate_count - self.gates.len();
+
+        let dead_signals: Vec<u32> = self
+            .nodes
+            .iter()
+            .filter(|node| !live_nodes.contains(&node.id))
+            .flat_map(|node| node.signals.clone())
+            .collect();
+        for signal in &dead_signals {
+            self.vars.remove(signal);
+            self.signal_names.remove(signal);
+        }
+        self.nodes.retain(|node| live_nodes.contains(&node.id));
+
+        debug!(
+            "Eliminated {} signals and {} gates not contributing to the outputs",
+            dead_signals.len(),
+            dead_gates
+        );
+
+        (dead_signals.len(), dead_gates)
+    }
+
+    /// Evaluates the gates whose inputs are both constants, replacing their outputs with constant signals.
+    /// Witness hints are kept, as a constant output would be constrained to its value.
+    /// Constants left unused by the folded gates are removed, so running the pass again has no effect.
+    /// Returns the number of folded gates.
+    pub fn fold_constants(&mut self, prime: &BigInt) -> Result<usize, CircuitError> {
+        let readers = self.gate_readers().clone();
+        let nodes: HashMap<u32, &Node> = self.nodes.iter().map(|node| (node.id, node)).collect();
+        let gates: HashMap<GateId, &ArithmeticGate> = self
+            .gates
+            .iter()
+            .filter(|gate| !self.hint_gates.contains(&gate.id))
+            .map(|gate| (gate.id, gate))
+            .collect();
+        let mut values: HashMap<u32, BigInt> = self
+            .nodes
+            .iter()
+            .filter_map(|node| Some((node.id, self.get_node_value(node)?)))
+            .collect();
+
+        // Folding a gate can make the gates that read its output foldable, so they're checked again after it
+        let mut worklist: Vec<GateId> = gates
+            .values()
+            .filter(|gate| {
+                gate.get_inputs()
+                    .iter()
+                    .all(|input| values.contains_key(input))
+            })
+            .map(|gate| gate.id)
+            .collect();
+        let mut folded: HashSet<GateId> = HashSet::new();
+        let mut constants: Vec<(u32, BigInt)> = Vec::new();
+        while let Some(gate_id) = worklist.pop() {
+            let gate = match gates.get(&gate_id) {
+                Some(gate) if !folded.contains(&gate_id) => gate,
+                _ => continue,
+            };
+            let inputs = match gate
+                .get_inputs()
+                .iter()
+                .map(|input| values.get(input))
+                .collect::<Option<Vec<&BigInt>>>()
+            {
+                Some(inputs) => inputs,
+                None => continue,
+            };
+            let value = match gate
+                .gate_type
+                .evaluate(inputs[0], inputs.get(1).copied(), prime)?
+            {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match values.get(&gate.output) {
+                Some(output_value) if *output_value != value => {
+                    return Err(CircuitError::ConstantValueAlreadySet);
+                }
+                Some(_) => {}
+                None => {
+                    // The output is connected to the constant once the gates are removed, so that readers see it
+                    let output_node = nodes.get(&gate.output).ok_or(CircuitError::NodeNotFound)?;
+                    constants.push((output_node.signals[0], value.clone()));
+                    values.insert(gate.output, value);
+                }
+            }
+
+            debug!("Folded {:?}", gate);
+            folded.insert(gate_id);
+            worklist.extend(
+                readers
+                    .get(&gate.output)
+                    .into_iter()
+                    .flatten()
+                    .map(|&(reader_id, _)| reader_id),
+            );
+        }
+
+        if folded.is_empty() {
+            return Ok(0);
+        }
+
+        self.gates.retain(|gate| !folded.contains(&gate.id));
+        self.gate_readers = None;
+        for gate_id in &folded {
+            self.gate_meta.remove(gate_id);
+        }
+        for (signal_id, value) in constants {
+            let const_id = self.add_const(value)?;
+            self.add_connection(const_id, signal_id)?;
+        }
+        self.remove_unused_constants();
+
+        Ok(folded.len())
+    }
+
+    /// Removes the nodes holding only constants that are no longer read by any gate, output or constraint.
+    fn remove_unused_constants(&mut self) {
+        let used_nodes: HashSet<u32> = self
+            .gates
+            .iter()
+            .flat_map(|gate| gate.get_inputs().into_iter().chain([gate.output]))
+            .chain(
+                self.custom_gates
+                    .iter()
+                    .flat_map(|gate| gate.inputs.iter().chain([&gate.output]).copied()),
+            )
+            .collect();
+        let used_signals: HashSet<u32> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .chain(self.constraints.iter().flat_map(|(lhs, rhs)| [lhs, rhs]))
+            .copied()
+            .collect();
+
+        let unused_constants: Vec<u32> = self
+            .nodes
+            .iter()
+            .filter(|node| !used_nodes.contains(&node.id))
+            .filter(|node| {
+                node.signals.iter().all(|signal| {
+                    !used_signals.contains(signal) && matches!(self.vars.get(signal), Some(Some(_)))
+                })
+            })
+            .flat_map(|node| node.signals.clone())
+            .collect();
+
+        for signal in &unused_constants {
+            self.vars.remove(signal);
+        }
+        self.nodes.retain(|node| {
+            node.signals
+                .iter()
+                .any(|signal| self.vars.contains_key(signal))
+        });
+    }
+
+    /// Merges the gates computing the same operation on the same inputs, connecting the output of each duplicate gate
+    /// to the output of the gate it's merged into.
+    /// Returns the number of removed gates.
+    pub fn cse(&mut self) -> Result<usize, CircuitError> {
+        let mut removed = 0;
+
+        // Merging outputs can make the gates that read them identical, so repeat until no duplicate is left
+        loop {
+            let mut canonical_gates: HashMap<GateKey, &ArithmeticGate> = HashMap::new();
+            let mut duplicates: Vec<(GateId, GateId, u32, u32)> = Vec::new();
+            for gate in &self.gates {
+                let key = GateKey::new(gate);
+                match canonical_gates.get(&key) {
+                    Some(canonical) => {
+                        duplicates.push((gate.id, canonical.id, canonical.output, gate.output))
+                    }
+                    None => {
+                        canonical_gates.insert(key, gate);
+                    }
+                }
+            }
+
+            if duplicates.is_empty() {
+                break;
+            }
+
+            // Nodes are replaced when merged, so their first signals are kept to connect them
+            let mut connections = Vec::new();
+            let mut duplicate_ids = HashSet::new();
+            for &(gate_id, canonical_id, canonical_output, duplicate_output) in &duplicates {
+                connections.push((
+                    self.get_node(canonical_output)?.signals[0],
+                    self.get_node(duplicate_output)?.signals[0],
+                ));
+                duplicate_ids.insert(gate_id);
+
+                // The merged gate keeps its source location, or takes the one of the duplicate if it has none
+                if let Some(meta) = self.gate_meta.remove(&gate_id) {
+                    self.gate_meta.entry(canonical_id).or_insert(meta);
+                }
+                // It's only a hint if both gates are, as the duplicate output may be constrained
+                if !self.hint_gates.remove(&gate_id) {
+                    self.hint_gates.remove(&canonical_id);
+                }
+            }
+            self.gates.retain(|gate| !duplicate_ids.contains(&gate.id));
+            self.gate_readers = None;
+            for (canonical_signal, duplicate_signal) in connections {
+                self.add_connection(canonical_signal, duplicate_signal)?;
+            }
+
+            debug!("Merged {} duplicate gates", duplicates.len());
+            removed += duplicates.len();
+        }
+
+        Ok(removed)
+    }
+
+    /// Replaces the comparison gates with field operations, so that the circuit can be exported to formats without
+    /// comparisons like R1CS. Operands of `<`, `<=`, `>` and `>=` are assumed to fit in `bits` bits.
+    /// Hint gates are left as they are, since they aren't constrained.
+    /// The lowered gates read new undriven signals, which are advice values to be provided along with the inputs:
+    /// - `a >= b` is the top bit of `a - b + 2^bits`, decomposed into `bits + 1` signals constrained to be boolean
+    ///   and to add up to it. The other ordering comparisons are derived from it.
+    /// - `a == b` is `1 - d * inv` for `d = a - b`, with `d * (a == b)` constrained to be zero.
+    /// Returns the number of lowered gates.
+    pub fn lower_comparisons(&mut self, bits: u32) -> Result<usize, CircuitError> {
+        let comparisons: Vec<GateId> = self
+            .gates
+            .iter()
+            .filter(|gate| !self.hint_gates.contains(&gate.id))
+            .filter(|gate| {
+                matches!(
+                    gate.gate_type,
+                    AGateType::AEq
+                        | AGateType::AGEq
+                        | AGateType::AGt
+                        | AGateType::ALEq
+                        | AGateType::ALt
+                        | AGateType::ANeq
+                )
+            })
+            .map(|gate| gate.id)
+            .collect();
+
+        for gate_id in &comparisons {
+            let index = self
+                .gates
+                .iter()
+                .position(|gate| gate.id == *gate_id)
+                .ok_or(CircuitError::GateNotFound)?;
+            let gate = self.gates.remove(index);
+            self.gate_readers = None;
+            self.gate_meta.remove(&gate.id);
+
+            // Nodes are replaced when merged, so gates are added on their first signals
+            let lhs = self.get_node(gate.lh_input)?.signals[0];
+            let rhs_input = gate.rh_input.ok_or(CircuitError::NodeNotFound)?;
+            let rhs = self.get_node(rhs_input)?.signals[0];
+            let output = self.get_node(gate.output)?.signals[0];
+
+            let result = match gate.gate_type {
+                AGateType::AGEq => self.add_greater_eq(lhs, rhs, bits)?,
+                AGateType::ALEq => self.add_greater_eq(rhs, lhs, bits)?,
+                AGateType::ALt => {
+                    let greater_eq = self.add_greater_eq(lhs, rhs, bits)?;
+                    self.add_not(greater_eq)?
+                }
+                AGateType::AGt => {
+                    let greater_eq = self.add_greater_eq(rhs, lhs, bits)?;
+                    self.add_not(greater_eq)?
+                }
+                AGateType::AEq => self.add_is_equal(lhs, rhs)?,
+                _ => {
+                    let is_equal = self.add_is_equal(lhs, rhs)?;
+                    self.add_not(is_equal)?
+                }
+            };
+            self.add_connection(result, output)?;
+
+            debug!("Lowered {:?}", gate);
+        }
+
+        Ok(comparisons.len())
+    }
+
+    /// Adds the gates computing `lhs >= rhs` for operands of the given number of bits, returning the result signal.
+    fn add_greater_eq(&mut self, lhs: u32, rhs: u32, bits: u32) -> Result<u32, CircuitError> {
+        let offset = self.add_const(BigInt::from(1u32) << bits as usize)?;
+        let diff = self.add_fresh_gate(AGateType::ASub, lhs, rhs)?;
+        let shifted = self.add_fresh_gate(AGateType::AAdd, diff, offset)?;
+
+        // The top bit of the shifted difference is set when it isn't negative
+        let shifted_bits = self.add_bit_decomposition(shifted, bits + 1)?;
+
+        Ok(shifted_bits[bits as usize])
+    }
+
+    /// Decomposes a signal into the given number of bits, like the circomlib `Num2Bits` template,
+    /// returning the bit signals from the least significant one.
+    /// The bits are new signals without gates driving them, which must be provided along with the inputs.
+    /// They're constrained to be boolean and their weighted sum to be equal to the signal.
+    pub fn add_bit_decomposition(
+        &mut self,
+        signal: u32,
+        bits: u32,
+    ) -> Result<Vec<u32>, CircuitError> {
+        if !self.contains_var(&signal) {
+            return Err(CircuitError::VariableNotDeclared);
+        }
+
+        let mut bit_signals = Vec::with_capacity(bits as usize);
+        let mut sum = self.add_const(BigInt::from(0u32))?;
+        for position in 0..bits {
+            let bit = self.add_fresh_signal()?;
+            let square = self.add_fresh_gate(AGateType::AMul, bit, bit)?;
+            self.add_constraint(square, bit)?;
+
+            let weight = self.add_const(BigInt::from(1u32) << position as usize)?;
+            let term = self.add_fresh_gate(AGateType::ACMul, bit, weight)?;
+            sum = self.add_fresh_gate(AGateType::AAdd, sum, term)?;
+            bit_signals.push(bit);
+        }
+        self.add_constraint(sum, signal)?;
+
+        Ok(bit_signals)
+    }
+
+    /// Adds the gates computing `lhs == rhs`, returning the result signal.
+    fn add_is_equal(&mut self, lhs: u32, rhs: u32) -> Result<u32, CircuitError> {
+        let diff = self.add_fresh_gate(AGateType::ASub, lhs, rhs)?;
+        let inverse = self.add_fresh_signal()?;
+        let product = self.add_fresh_gate(AGateType::AMul, diff, inverse)?;
+        let is_equal = self.add_not(product)?;
+
+        // A non zero difference must give a zero result, whatever the inverse advice is
+        let check = self.add_fresh_gate(AGateType::AMul, diff, is_equal)?;
+        let zero = self.add_const(BigInt::from(0u32))?;
+        self.add_constraint(check, zero)?;
+
+        Ok(is_equal)
+    }
+
+    /// Adds the gate computing `1 - value` for a boolean value, returning the result signal.
+    fn add_not(&mut self, value: u32) -> Result<u32, CircuitError> {
+        let one = self.add_const(BigInt::from(1u32))?;
+        self.add_fresh_gate(AGateType::ASub, one, value)
+    }
+
+    /// Adds a new signal with a random id, returning it.
+    fn add_fresh_signal(&mut self) -> Result<u32, CircuitError> {
+        let mut id = generate_u32();
+        while self.contains_var(&id) {
+            id = generate_u32();
+        }
+
+        self.add_signal(id)?;
+        Ok(id)
+    }
+
+    /// Adds a gate with a new output signal, returning the output signal.
+    fn add_fresh_gate(
+        &mut self,
+        gate_type: AGateType,
+        lhs_id: u32,
+        rhs_id: u32,
+    ) -> Result<u32, CircuitError> {
+        let output_id = self.add_fresh_signal()?;
+        self.add_gate(gate_type, lhs_id, Some(rhs_id), output_id)?;
+        Ok(output_id)
+    }
+
+    /// Returns the node with the given id.
+    pub fn get_node(&self, node_id: u32) -> Result<Node, CircuitError> {
+        self.nodes
+            .iter()
+            .find(|node| node.id == node_id)
+            .cloned()
+            .ok_or(CircuitError::NodeNotFound)
+    }
+
+    /// Returns the constant value of a node, if it contains a constant signal.
+    pub fn get_node_value(&self, node: &Node) -> Option<BigInt> {
+        node.signals
+            .iter()
+            .find_map(|signal| self.vars.get(signal).cloned().flatten())
+    }
+
+    /// Returns a portable JSON representation of the circuit.
+    /// Constant values are written as decimal strings, so they can be read without big integer support.
+    pub fn to_json(&self) -> Value {
+        let mut signals: Vec<u32> = self
+            .vars
+            .iter()
+            .filter(|(_, value)| value.is_none())
+            .map(|(&id, _)| id)
+            .collect();
+        signals.sort_unstable();
+
+        let constants: serde_json::Map<String, Value> = self
+            .vars
+            .iter()
+            .filter_map(|(id, value)| {
+                value
+                    .as_ref()
+                    .map(|value| (id.to_string(), Value::String(value.to_string())))
+            })
+            .collect();
+
+        let nodes: Vec<Value> = self
+            .nodes
+            .iter()
+            .map(|node| json!({ "id": node.id, "signals": node.signals }))
+            .collect();
+
+        let gates: Vec<Value> = self
+            .gates
+            .iter()
+            .map(|gate| {
+                json!({
+                    "id": gate.id,
+                    "type": format!("{:?}", gate.gate_type),
+                    "lh_input": gate.lh_input,
+                    "rh_input": gate.rh_input,
+                    "output": gate.output,
+                })
+            })
+            .collect();
+
+        let mut sorted_hint_gates: Vec<GateId> = self.hint_gates.iter().copied().collect();
+        sorted_hint_gates.sort_unstable();
+
+        json!({
+            "version": CIRCUIT_JSON_VERSION,
+            "signals": signals,
+            "constants": constants,
+            "nodes": nodes,
+            "gates": gates,
+            "constraints": self.constraints,
+            "inputs": self.inputs,
+            "outputs": self.outputs,
+            "custom_gate_types": self.custom_gate_types,
+            "custom_gates": self.custom_gates,
+            "signal_names": self.signal_names,
+            "hint_gates": sorted_hint_gates,
+        })
+    }
+
+    /// Returns the nodes of the circuit.
+    pub fn get_nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Returns the gates of the circuit.
+    pub fn get_gates(&self) -> &[ArithmeticGate] {
+        &self.gates
+    }
+
+    /// Returns an iterator over the gates of the circuit, borrowing them.
+    pub fn gates_iter(&self) -> impl Iterator<Item = &ArithmeticGate> {
+        self.gates.iter()
+    }
+
+    /// Returns an iterator over the signals of the circuit with their constant value, if any, in no particular order.
+    pub fn signals_iter(&self) -> impl Iterator<Item = (u32, Option<&BigInt>)> {
+        self.vars.iter().map(|(&id, value)| (id, value.as_ref()))
+    }
+
+    /// Returns the custom gates of the circuit.
+    pub fn get_custom_gates(&self) -> &[CustomGate] {
+        &self.custom_gates
+    }
+
+    /// Returns the equality constraints of the circuit.
+    pub fn get_constraints(&self) -> &[(u32, u32)] {
+        &self.constraints
+    }
+
+    /// Returns the input signals of the circuit.
+    pub fn get_inputs(&self) -> &[u32] {
+        &self.inputs
+    }
+
+    /// Returns the output signals of the circuit.
+    pub fn get_outputs(&self) -> &[u32] {
+        &self.outputs
+    }
+
+    /// Returns the input signals of the circuit by name, like `in[0]` for an input array `in` of the main template.
+    /// Unnamed inputs are listed as `signal_{id}`.
+    pub fn public_inputs(&self) -> HashMap<String, u32> {
+        self.inputs
+            .iter()
+            .map(|&signal| (self.signal_label(signal), signal))
+            .collect()
+    }
+
+    /// Returns the output signals of the circuit by name, like `out` for an output `out` of the main template.
+    /// Unnamed outputs are listed as `signal_{id}`.
+    pub fn public_outputs(&self) -> HashMap<String, u32> {
+        self.outputs
+            .iter()
+            .map(|&signal| (self.signal_label(signal), signal))
+            .collect()
+    }
+
+    /// Reconstructs a circuit from its JSON representation, as produced by `to_json`.
+    /// Checks that every signal belongs to exactly one node and that gates, constraints and outputs refer to
+    /// existing nodes and signals.
+    pub fn from_json(value: &Value) -> Result<ArithmeticCircuit, CircuitError> {
+        let invalid = |message: String| CircuitError::InvalidCircuitJson(message);
+        let circuit_json = CircuitJson::deserialize(value).map_err(|e| invalid(e.to_string()))?;
+
+        // Earlier versions are a subset of the current one
+        if circuit_json.version == 0 || circuit_json.version > CIRCUIT_JSON_VERSION {
+            return Err(invalid(format!(
+                "Unsupported version {}, expected at most {}",
+                circuit_json.version, CIRCUIT_JSON_VERSION
+            )));
+        }
+
+        let mut circuit = ArithmeticCircuit::new();
+        for id in circuit_json.signals {
+            if circuit.vars.insert(id, None).is_some() {
+                return Err(invalid(format!("Signal {} is declared twice", id)));
+            }
+        }
+        for (id, value) in circuit_json.constants {
+            let id: u32 = id.parse()?;
+            let value = BigInt::parse_bytes(value.as_bytes(), 10)
+                .ok_or_else(|| invalid(format!("Invalid value for constant {}", id)))?;
+            if circuit.vars.insert(id, Some(value.clone())).is_some() {
+                return Err(invalid(format!("Signal {} is declared twice", id)));
+            }
+            circuit.constant_ids.insert(value, id);
+        }
+
+        // Every signal must belong to exactly one node
+        let mut node_signals = HashSet::new();
+        for node in &circuit_json.nodes {
+            for signal in &node.signals {
+                if !circuit.contains_var(signal) {
+                    return Err(invalid(format!(
+                        "Node {} contains undeclared signal {}",
+                        node.id, signal
+                    )));
+                }
+                if !node_signals.insert(*signal) {
+                    return Err(invalid(format!("Signal {} is in several nodes", signal)));
+                }
+            }
+        }
+        if node_signals.len() != circuit.vars.len() {
+            return Err(invalid("Some signals don't belong to any node".to_string()));
+        }
+        circuit.nodes = circuit_json.nodes;
+
+        let node_ids: HashSet<u32> = circuit.nodes.iter().map(|node| node.id).collect();
+        let mut gate_ids = HashSet::new();
+        for gate in &circuit_json.gates {
+            if !gate_ids.insert(gate.id) {
+                return Err(invalid(format!("Gate {} is declared twice", gate.id)));
+            }
+            if gate.gate_type.is_unary() != gate.rh_input.is_none() {
+                return Err(invalid(format!(
+                    "Gate {} of type {:?} has {} inputs",
+                    gate.id,
+                    gate.gate_type,
+                    gate.get_inputs().len()
+                )));
+            }
+            for node_id in gate.get_inputs().into_iter().chain([gate.output]) {
+                if !node_ids.contains(&node_id) {
+                    return Err(invalid(format!(
+                        "Gate {} refers to unknown node {}",
+                        gate.id, node_id
+                    )));
+                }
+            }
+        }
+        circuit.gates = circuit_json.gates;
+        circuit.gate_readers = None;
+        circuit.next_gate_id = None;
+
+        for gate in &circuit_json.custom_gates {
+            let arity = circuit_json
+                .custom_gate_types
+                .get(&gate.name)
+                .ok_or_else(|| {
+                    invalid(format!("Custom gate type {} is not registered", gate.name))
+                })?;
+            if gate.inputs.len() != *arity {
+                return Err(invalid(format!(
+                    "Custom gate {} has {} inputs, expected {}",
+                    gate.name,
+                    gate.inputs.len(),
+                    arity
+                )));
+            }
+            for node_id in gate.inputs.iter().chain([&gate.output]) {
+                if !node_ids.contains(node_id) {
+                    return Err(invalid(format!(
+                        "Custom gate {} refers to unknown node {}",
+                        gate.name, node_id
+                    )));
+                }
+            }
+        }
+        circuit.custom_gate_types = circuit_json.custom_gate_types;
+        circuit.custom_gates = circuit_json.custom_gates;
+
+        for signal in circuit_json
+            .constraints
+            .iter()
+            .flat_map(|(lhs, rhs)| [lhs, rhs])
+            .chain(circuit_json.inputs.iter())
+            .chain(circuit_json.outputs.iter())
+        {
+            if !circuit.contains_var(signal) {
+                return Err(invalid(format!("Undeclared signal {}", signal)));
+            }
+        }
+        circuit.constraints = circuit_json.constraints;
+        circuit.inputs = circuit_json.inputs;
+        circuit.outputs = circuit_json.outputs;
+
+        if let Some(signal) = circuit_json
+            .signal_names
+            .keys()
+            .find(|signal| !circuit.contains_var(signal))
+        {
+            return Err(invalid(format!("Name of undeclared signal {}", signal)));
+        }
+        circuit.signal_names = circuit_json.signal_names;
+
+        if let Some(gate_id) = circuit_json
+            .hint_gates
+            .iter()
+            .find(|gate_id| !circuit.gates.iter().any(|gate| gate.id == **gate_id))
+        {
+            return Err(invalid(format!("Unknown hint gate {}", gate_id)));
+        }
+        circuit.hint_gates = circuit_json.hint_gates.into_iter().collect();
+
+        Ok(circuit)
+    }
+
+    /// Returns the ids of the gates sorted so that every gate comes after the gates driving its inputs.
+    pub fn topological_sort(&self) -> Result<Vec<GateId>, CircuitError> {
+        let drivers: HashMap<u32, usize> = self
+            .gates
+            .iter()
+            .enumerate()
+            .map(|(index, gate)| (gate.output, index))
+            .collect();
+
+        let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); self.gates.len()];
+        let mut pending = vec![0; self.gates.len()];
+        for (index, gate) in self.gates.iter().enumerate() {
+            for input in gate.get_inputs() {
+                if let Some(&driver) = drivers.get(&input) {
+                    dependents[driver].push(index);
+                    pending[index] += 1;
+                }
+            }
+        }
+
+        let mut queue: VecDeque<usize> = (0..self.gates.len())
+            .filter(|&index| pending[index] == 0)
+            .collect();
+        let mut sorted = Vec::with_capacity(self.gates.len());
+        while let Some(index) = queue.pop_front() {
+            sorted.push(self.gates[index].id);
+            for &dependent in &dependents[index] {
+                pending[dependent] -= 1;
+                if pending[dependent] == 0 {
+                    queue.push_back(dependent);
+                }
+            }
+        }
+
+        // Gates still waiting for an input are part of a cycle or depend on one
+        if let Some(index) = (0..self.gates.len()).find(|&index| pending[index] > 0) {
+            return Err(CircuitError::CircuitCycleDetected {
+                gate_id: self.gates[index].id,
+            });
+        }
+
+        Ok(sorted)
+    }
+
+    /// Evaluates the circuit on the given input values, returning the value of every signal.
+    /// Inputs map signal ids to field elements, and must cover every non constant signal not driven by a gate.
+    /// Gates are evaluated in topological order, and equality constraints are checked on the result.
+    pub fn simulate(
+        &self,
+        inputs: &HashMap<u32, BigInt>,
+        prime: &BigInt,
+    ) -> Result<HashMap<u32, BigInt>, CircuitError> {
+        if let Some(gate) = self.custom_gates.first() {
+            return Err(CircuitError::UnsupportedGateType(format!(
+                "custom gate {} in simulation",
+                gate.name
+            )));
+        }
+
+        // Set the values known before evaluation, from constants and inputs
+        let mut node_values: HashMap<u32, BigInt> = HashMap::new();
+        for node in &self.nodes {
+            for signal in &node.signals {
+                let value = match (self.vars.get(signal).cloned().flatten(), inputs.get(signal)) {
+                    (Some(value), _) => modulo(&value, prime),
+                    (None, Some(value)) => modulo(value, prime),
+                    (None, None) => continue,
+                };
+                set_node_value(&mut node_values, node.id, value)?;
+            }
+        }
+
+        let gates: HashMap<GateId, &ArithmeticGate> =
+            self.gates.iter().map(|gate| (gate.id, gate)).collect();
+        for id in self.topological_sort()? {
+            let gate = gates.get(&id).ok_or(CircuitError::GateNotFound)?;
+            let get_value = |node_id: u32| {
+                node_values.get(&node_id).cloned().ok_or_else(|| {
+                    let signal = self.get_node(node_id).map(|node| node.signals[0]);
+                    CircuitError::SignalValueMissing(signal.unwrap_or(node_id))
+                })
+            };
+
+            let rhs_value = gate.rh_input.map(&get_value).transpose()?;
+            let value = gate
+                .gate_type
+                .evaluate(&get_value(gate.lh_input)?, rhs_value.as_ref(), prime)?
+                .ok_or_else(|| {
+                    CircuitError::UnsupportedGateType(format!("{:?} in simulation", gate.gate_type))
+                })?;
+            set_node_value(&mut node_values, gate.output, value)?;
+        }
+
+        let mut values = HashMap::new();
+        for node in &self.nodes {
+            let value = node_values
+                .get(&node.id)
+                .ok_or(CircuitError::SignalValueMissing(node.signals[0]))?;
+            for &signal in &node.signals {
+                values.insert(signal, value.clone());
+            }
+        }
+
+        for (lhs, rhs) in &self.constraints {
+            if values.get(lhs) != values.get(rhs) {
+                return Err(CircuitError::ConstraintNotSatisfied(format!(
+                    "{} === {}",
+                    lhs, rhs
+                )));
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Checks if both circuits have the same structure up to the renaming of their signals and nodes,
+    /// with inputs and outputs in the same order.
+    /// Nodes are labeled by Weisfeiler-Leman refinement on the gates and constraints linking them, until the labels are stable.
+    /// Different labels prove that the circuits aren't isomorphic, but some rare non isomorphic circuits get the same labels.
+    pub fn is_isomorphic_to(&self, other: &ArithmeticCircuit) -> bool {
+        if self.nodes.len() != other.nodes.len()
+            || self.gates.len() != other.gates.len()
+            || self.custom_gates.len() != other.custom_gates.len()
+            || self.constraints.len() != other.constraints.len()
+            || self.inputs.len() != other.inputs.len()
+            || self.outputs.len() != other.outputs.len()
+        {
+            return false;
+        }
+
+        let mut labels = self.initial_node_labels();
+        let mut other_labels = other.initial_node_labels();
+        let mut class_count = 0;
+        loop {
+            if sorted_labels(&labels) != sorted_labels(&other_labels) {
+                return false;
+            }
+
+            // Refinement only splits classes, so the labels are stable when their number stays the same
+            let refined_class_count = labels.values().collect::<HashSet<_>>().len();
+            if refined_class_count == class_count {
+                return true;
+            }
+            class_count = refined_class_count;
+
+            labels = self.refine_node_labels(&labels);
+            other_labels = other.refine_node_labels(&other_labels);
+        }
+    }
+
+    /// Checks if both circuits give the same outputs on random inputs, matched by position, in the given field.
+    /// Trials where the simulation of both circuits fails, like on a constraint not satisfied by random inputs, count as agreeing.
+    pub fn probabilistically_equivalent(
+        &self,
+        other: &ArithmeticCircuit,
+        num_trials: usize,
+        prime: &BigInt,
+    ) -> bool {
+        if self.inputs.len() != other.inputs.len() || self.outputs.len() != other.outputs.len() {
+            return false;
+        }
+
+        let mut rng = thread_rng();
+        for _ in 0..num_trials {
+            let values: Vec<BigInt> = (0..self.inputs.len())
+                .map(|_| {
+                    let bytes: [u8; 32] = rng.gen();
+                    modulo(&BigInt::from_bytes_be(Sign::Plus, &bytes), prime)
+                })
+                .collect();
+            let inputs = self
+                .inputs
+                .iter()
+                .copied()
+                .zip(values.iter().cloned())
+                .collect();
+            let other_inputs = other.inputs.iter().copied().zip(values).collect();
+
+            match (
+                self.simulate(&inputs, prime),
+                other.simulate(&other_inputs, prime),
+            ) {
+                (Ok(result), Ok(other_result)) => {
+                    let outputs = self.outputs.iter().map(|id| result.get(id));
+                    let other_outputs = other.outputs.iter().map(|id| other_result.get(id));
+                    if !outputs.eq(other_outputs) {
+                        return false;
+                    }
+                }
+                (Err(_), Err(_)) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    /// Returns the labels of the nodes before refinement, from their constant value and their input and output positions.
+    fn initial_node_labels(&self) -> HashMap<u32, u64> {
+        self.nodes
+            .iter()
+            .map(|node| {
+                let value = self.get_node_value(node);
+                let positions = |signals: &[u32]| -> Vec<usize> {
+                    signals
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, signal)| node.signals.contains(signal))
+                        .map(|(position, _)| position)
+                        .collect()
+                };
+                let label = hash_of(&(value, positions(&self.inputs), positions(&self.outputs)));
+                (node.id, label)
+            })
+            .collect()
+    }
+
+    /// Returns the labels of the nodes after a refinement round, combining their label with those of their neighbors.
+    fn refine_node_labels(&self, labels: &HashMap<u32, u64>) -> HashMap<u32, u64> {
+        let mut neighbors: HashMap<u32, Vec<u64>> = self
+            .nodes
+            .iter()
+            .map(|node| (node.id, Vec::new()))
+            .collect();
+        let mut add_neighbor = |node_id: u32, label: u64| {
+            if let Some(node_neighbors) = neighbors.get_mut(&node_id) {
+                node_neighbors.push(label);
+            }
+        };
+
+        for gate in &self.gates {
+            let (lhs, output) = (labels[&gate.lh_input], labels[&gate.output]);
+            let rh_input = match gate.rh_input {
+                Some(rh_input) => rh_input,
+                None => {
+                    add_neighbor(gate.lh_input, hash_of(&(&gate.gate_type, "in", output)));
+                    add_neighbor(gate.output, hash_of(&(&gate.gate_type, "out", lhs)));
+                    continue;
+                }
+            };
+            let rhs = labels[&rh_input];
+            if gate.gate_type.is_commutative() {
+                add_neighbor(
+                    gate.lh_input,
+                    hash_of(&(&gate.gate_type, "in", rhs, output)),
+                );
+                add_neighbor(rh_input, hash_of(&(&gate.gate_type, "in", lhs, output)));
+                add_neighbor(
+                    gate.output,
+                    hash_of(&(&gate.gate_type, "out", lhs.min(rhs), lhs.max(rhs))),
+                );
+            } else {
+                add_neighbor(
+                    gate.lh_input,
+                    hash_of(&(&gate.gate_type, "lhs", rhs, output)),
+                );
+                add_neighbor(rh_input, hash_of(&(&gate.gate_type, "rhs", lhs, output)));
+                add_neighbor(gate.output, hash_of(&(&gate.gate_type, "out", lhs, rhs)));
+            }
+        }
+
+        for gate in &self.custom_gates {
+            let inputs: Vec<u64> = gate.inputs.iter().map(|input| labels[input]).collect();
+            let output = labels[&gate.output];
+            for (position, input) in gate.inputs.iter().enumerate() {
+                add_neighbor(*input, hash_of(&(&gate.name, position, &inputs, output)));
+            }
+            add_neighbor(gate.output, hash_of(&(&gate.name, "out", &inputs)));
+        }
+
+        for (lhs, rhs) in &self.constraints {
+            if let (Ok(lhs_node), Ok(rhs_node)) =
+                (self.get_signal_node(*lhs), self.get_signal_node(*rhs))
+            {
+                add_neighbor(lhs_node.id, hash_of(&("constraint", labels[&rhs_node.id])));
+                add_neighbor(rhs_node.id, hash_of(&("constraint", labels[&lhs_node.id])));
+            }
+        }
+
+        neighbors
+            .into_iter()
+            .map(|(node_id, mut node_neighbors)| {
+                node_neighbors.sort_unstable();
+                (node_id, hash_of(&(labels[&node_id], node_neighbors)))
+            })
+            .collect()
+    }
+
+    /// Returns the depth of the circuit, which is the length in gates of its longest path.
+    pub fn compute_depth(&self) -> Result<u32, CircuitError> {
+        Ok(self
+            .get_node_depths(|_| true)?
+            .into_values()
+            .max()
+            .unwrap_or(0))
+    }
+
+    /// Returns the multiplicative depth of the circuit, which only counts the multiplication gates of its paths.
+    pub fn compute_multiplicative_depth(&self) -> Result<u32, CircuitError> {
+        Ok(self
+            .get_node_depths(|gate_type| *gate_type == AGateType::AMul)?
+            .into_values()
+            .max()
+            .unwrap_or(0))
+    }
+
+    /// Returns the depth of a signal, which is the length in gates of the longest path leading to it.
+    /// Signals not driven by any gate have a depth of 0.
+    pub fn signal_depth(&self, signal_id: u32) -> Result<u32, CircuitError> {
+        let node = self.get_signal_node(signal_id)?;
+
+        Ok(self
+            .get_node_depths(|_| true)?
+            .get(&node.id)
+            .copied()
+            .unwrap_or(0))
+    }
+
+    /// Computes the depth of the nodes driven by gates, only counting the gates of the given types.
+    fn get_node_depths(
+        &self,
+        counts: impl Fn(&AGateType) -> bool,
+    ) -> Result<HashMap<u32, u32>, CircuitError> {
+        let gates: HashMap<GateId, &ArithmeticGate> =
+            self.gates.iter().map(|gate| (gate.id, gate)).collect();
+
+        let mut depths: HashMap<u32, u32> = HashMap::new();
+        for id in self.topological_sort()? {
+            let gate = gates.get(&id).ok_or(CircuitError::GateNotFound)?;
+            let input_depth = gate
+                .get_inputs()
+                .iter()
+                .filter_map(|input| depths.get(input))
+                .copied()
+                .max()
+                .unwrap_or(0);
+
+            let depth = input_depth + u32::from(counts(&gate.gate_type));
+            depths.insert(gate.output, depth);
+        }
+
+        Ok(depths)
+    }
+
+    /// Returns the fan-out of each node, which is the number of gates reading it as an input.
+    /// Gates are wired to nodes, so all the signals of a node share its fan-out.
+    pub fn fan_out_histogram(&self) -> HashMap<u32, usize> {
+        let mut fan_outs: HashMap<u32, usize> =
+            self.nodes.iter().map(|node| (node.id, 0)).collect();
+
+        for gate in &self.gates {
+            *fan_outs.entry(gate.lh_input).or_insert(0) += 1;
+            if let Some(rh_input) = gate.rh_input.filter(|&input| input != gate.lh_input) {
+                *fan_outs.entry(rh_input).or_insert(0) += 1;
+            }
+        }
+
+        fan_outs
+    }
+
+    /// Returns the node with the highest fan-out along with its fan-out, or `None` for an empty circuit.
+    /// Ties are broken by the lowest node id.
+    pub fn max_fan_out(&self) -> Option<(u32, usize)> {
+        self.fan_out_histogram()
+            .into_iter()
+            .max_by(|(lh_id, lh_fan_out), (rh_id, rh_fan_out)| {
+                lh_fan_out.cmp(rh_fan_out).then(rh_id.cmp(lh_id))
+            })
+    }
+
+    /// Returns the sorted ids of the nodes whose fan-out is above the given threshold.
+    pub fn signals_above_fan_out_threshold(&self, threshold: usize) -> Vec<u32> {
+        let mut node_ids: Vec<u32> = self
+            .fan_out_histogram()
+            .into_iter()
+            .filter(|&(_, fan_out)| fan_out > threshold)
+            .map(|(node_id, _)| node_id)
+            .collect();
+
+        node_ids.sort_unstable();
+        node_ids
+    }
+
+    /// Checks if the gates of the circuit form a cycle.
+    pub fn has_cycle(&self) -> bool {
+        matches!(
+            self.topological_sort(),
+            Err(CircuitError::CircuitCycleDetected { .. })
+        )
+    }
+
+    /// Returns the id of the last gate of a path of gates leading from a node to one of the target nodes, if any.
+    fn find_gate_path(&mut self, from: u32, targets: &[u32]) -> Option<GateId> {
+        let readers = self.gate_readers();
+
+        let mut visited = HashSet::from([from]);
+        let mut stack = vec![from];
+        while let Some(node_id) = stack.pop() {
+            for &(gate_id, output) in readers.get(&node_id).into_iter().flatten() {
+                if targets.contains(&output) {
+                    return Some(gate_id);
+                }
+                if visited.insert(output) {
+                    stack.push(output);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Builds the Rank-1 Constraint System of the circuit.
+    pub fn to_r1cs(&self, prime: &BigInt) -> Result<R1CSFile, CircuitError> {
+        R1CSFile::from_circuit(self, prime)
+    }
+
+    /// Converts the circuit into PLONK gates, with the selectors of the default `CustomGateRegistry`.
+    pub fn to_plonk_gates(&self, prime: &BigInt) -> Result<Vec<PlonkGate>, CircuitError> {
+        plonk::to_plonk_gates(self, prime, &CustomGateRegistry::default())
+    }
+
+    /// Converts the circuit into PLONK gates, with the selectors of the given registry.
+    pub fn to_plonk_gates_with_registry(
+        &self,
+        prime: &BigInt,
+        registry: &CustomGateRegistry,
+    ) -> Result<Vec<PlonkGate>, CircuitError> {
+        plonk::to_plonk_gates(self, prime, registry)
+    }
+
+    /// Writes the PLONK gates of the circuit as JSON, with the selectors of the default `CustomGateRegistry`.
+    pub fn to_plonk_json(&self, prime: &BigInt) -> Result<Value, CircuitError> {
+        plonk::to_plonk_json(self, prime, &CustomGateRegistry::default())
+    }
+
+    /// Writes the circuit in the Bristol gate-list format.
+    pub fn to_bristol(&self) -> Result<String, CircuitError> {
+        bristol::to_bristol(self)
+    }
+
+    /// Writes the circuit as a Graphviz DOT graph.
+    pub fn to_dot(&self) -> String {
+        dot::to_dot(self)
+    }
+
+    /// Returns the node containing the given signal.
+    pub fn get_signal_node(&self, signal_id: u32) -> Result<Node, CircuitError> {
+        for node in &self.nodes {
+            if node.signals.contains(&signal_id) {
+                return Ok(node.clone());
+            }
+        }
+
+        Err(CircuitError::NodeNotFound)
+    }
+
+    /// Checks if the variable exists
+    pub fn contains_var(&self, var: &u32) -> bool {
+        self.vars.contains_key(var)
+    }
+
+    /// Returns the number of gates in the circuit.
+    pub fn gate_count(&self) -> u32 {
+        self.gates.len() as u32
+    }
+
+    /// Returns the id for a new gate, which follows the highest id so that it stays unique after gates are removed.
+    fn next_gate_id(&mut self) -> GateId {
+        let gates = &self.gates;
+        *self
+            .next_gate_id
+            .get_or_insert_with(|| gates.iter().map(|gate| gate.id + 1).max().unwrap_or(0))
+    }
+
+    /// Returns the gates reading each node, building the index if gates were removed or rewired since the last use.
+    fn gate_readers(&mut self) -> &HashMap<u32, Vec<(GateId, u32)>> {
+        let gates = &self.gates;
+        self.gate_readers.get_or_insert_with(|| {
+            let mut readers = HashMap::new();
+            for gate in gates {
+                index_gate_reader(&mut readers, gate);
+            }
+            readers
+        })
+    }
+
+    /// Returns a size summary of the circuit.
+    pub fn stats(&self) -> CircuitStats {
+        let constant_count = self.vars.values().filter(|value| value.is_some()).count();
+
+        let mut gates_by_type = HashMap::new();
+        for gate in &self.gates {
+            *gates_by_type.entry(gate.gate_type.clone()).or_insert(0) += 1;
+        }
+
+        CircuitStats {
+            signal_count: self.vars.len() - constant_count,
+            constant_count,
+            gate_count: self.gates.len(),
+            gates_by_type,
+            connection_count: self
+                .nodes
+                .iter()
+                .map(|node| node.signals.len().saturating_sub(1))
+                .sum(),
+        }
+    }
+}
+
+/// Adds a gate to the index of the gates reading each node.
+fn index_gate_reader(readers: &mut HashMap<u32, Vec<(GateId, u32)>>, gate: &ArithmeticGate) {
+    readers
+        .entry(gate.lh_input)
+        .or_default()
+        .push((gate.id, gate.output));
+    if let Some(rh_input) = gate.rh_input.filter(|&input| input != gate.lh_input) {
+        readers
+            .entry(rh_input)
+            .or_default()
+            .push((gate.id, gate.output));
+    }
+}
+
+/// Sets the value of a node during simulation, checking that it doesn't conflict with a value already set.
+fn set_node_value(
+    node_values: &mut HashMap<u32, BigInt>,
+    node_id: u32,
+    value: BigInt,
+) -> Result<(), CircuitError> {
+    match node_values.get(&node_id) {
+        Some(current) if *current != value => Err(CircuitError::ConstraintNotSatisfied(format!(
+            "node {} holds both {} and {}",
+            node_id, current, value
+        ))),
+        Some(_) => Ok(()),
+        None => {
+            node_values.insert(node_id, value);
+            Ok(())
+        }
+    }
+}
+
+/// Returns the hash of a value, used to label nodes when comparing circuits.
+fn hash_of<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the sorted labels of the nodes, which only depend on the circuit structure.
+fn sorted_labels(labels: &HashMap<u32, u64>) -> Vec<u64> {
+    let mut sorted: Vec<u64> = labels.values().copied().collect();
+    sorted.sort_unstable();
+    sorted
+}
+
+/// JSON representation of a circuit, as read by `ArithmeticCircuit::from_json`.
+#[derive(Deserialize)]
+struct CircuitJson {
+    version: u32,
+    signals: Vec<u32>,
+    constants: HashMap<String, String>,
+    nodes: Vec<Node>,
+    gates: Vec<ArithmeticGate>,
+    constraints: Vec<(u32, u32)>,
+    #[serde(default)]
+    inputs: Vec<u32>,
+    outputs: Vec<u32>,
+    #[serde(default)]
+    custom_gate_types: HashMap<String, usize>,
+    #[serde(default)]
+    custom_gates: Vec<CustomGate>,
+    #[serde(default)]
+    signal_names: HashMap<u32, String>,
+    #[serde(default)]
+    hint_gates: Vec<GateId>,
+}
+
+#[allow(dead_code)]
+/// Represents a gate in its raw, unchecked form, used during parsing.
+pub struct UncheckedGate {
+    xref: usize,
+    yref: Option<usize>,
+    zref: usize,
+    gate_type: GateType,
+}
+
+impl UncheckedGate {
+    #[allow(dead_code)]
+    pub fn parse(captures: Captures) -> Result<Self, CircuitError> {
+        let xref: usize = captures.name("xref").unwrap().as_str().parse()?;
+        let yref: Option<usize> = captures
+            .name("yref")
+            .map(|yref| yref.as_str().parse())
+            .transpose()?;
+        let zref: usize = captures.name("zref").unwrap().as_str().parse()?;
+        let gate_type = captures.name("gate").unwrap().as_str();
+
+        let gate_type = match gate_type {
+            "XOR" => GateType::Xor,
+            "AND" => GateType::And,
+            "INV" => GateType::Inv,
+            _ => return Err(CircuitError::UnsupportedGateType(gate_type.to_string())),
+        };
+
+        Ok(Self {
+            xref,
+            yref,
+            zref,
+            gate_type,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CircuitError {
+    #[error("Cannot merge output nodes")]
+    CannotMergeOutputNodes,
+    #[error("Circuit contains a cycle through gate {gate_id}")]
+    CircuitCycleDetected { gate_id: GateId },
+    #[error("Circuit variable already declared")]
+    CircuitVariableAlreadyDeclared,
+    #[error("Constant value already set for variable")]
+    ConstantValueAlreadySet,
+    #[error("Constraint not satisfied: {0}")]
+    ConstraintNotSatisfied(String),
+    #[error("Custom gate already registered: {0}")]
+    CustomGateAlreadyRegistered(String),
+    #[error("Custom gate arity mismatch: {0}")]
+    CustomGateArityMismatch(String),
+    #[error("Custom gate not registered: {0}")]
+    CustomGateNotRegistered(String),
+    #[error("Gate evaluation error: {0}")]
+    GateEvaluationError(String),
+    #[error("Gate not found")]
+    GateNotFound,
+    #[error("Invalid circuit JSON: {0}")]
+    InvalidCircuitJson(String),
+    #[error("Invalid gate inputs: {0}")]
+    InvalidGateInputs(String),
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    ParseIntError(#[from] std::num::ParseIntError),
+    #[error("Node not found")]
+    NodeNotFound,
+    #[error("Missing value for signal {0}")]
+    SignalValueMissing(u32),
+    #[error("unsupported gate type: {0}")]
+    UnsupportedGateType(String),
+    #[error("Variable not declared")]
+    VariableNotDeclared,
+}
+
+impl From<CircuitError> for ProgramError {
+    fn from(e: CircuitError) -> Self {
+        ProgramError::CircuitError(e)
+    }
+}
+
+/// Fixtures shared by the tests of the crate.
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::ArithmeticCircuit;
+    use crate::{
+        builder::{CircuitBuilder, SignalHandle},
+        runtime::BN254_PRIME,
+    };
+    use num_bigint_dig::BigInt;
+
+    /// Returns the BN254 prime, the default field of the compiler.
+    pub fn bn254_prime() -> BigInt {
+        BigInt::parse_bytes(BN254_PRIME.as_bytes(), 10).unwrap()
+    }
+
+    /// Builds the circuit `a * b + 3`.
+    pub fn multiply_add() -> ArithmeticCircuit {
+        multiply_add_with_signals().0
+    }
+
+    /// Builds the circuit `a * b + 3`, along with its signals `a`, `b`, `a * b`, `3` and the output, in this order.
+    pub fn multiply_add_with_signals() -> (ArithmeticCircuit, [SignalHandle; 5]) {
+        let mut builder = CircuitBuilder::new();
+        let (a, b) = (builder.input().unwrap(), builder.input().unwrap());
+        let product = builder.mul(a, b).unwrap();
+        let three = builder.constant(BigInt::from(3)).unwrap();
+        let out = builder.add(product, three).unwrap();
+        builder.output(out).unwrap();
+
+        (builder.build(), [a, b, product, three, out])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::{bn254_prime, multiply_add};
+    use super::*;
+    use crate::{
+        builder::CircuitBuilder, compiler::compile_circom_source_with_config, program::BuildConfig,
+    };
+    use circom_circom_algebra::num_traits::{One, Zero};
+
+    /// Simulates a circuit, returning the values of its outputs in declaration order.
+    fn evaluate(circuit: &ArithmeticCircuit, inputs: &[i64]) -> Vec<BigInt> {
+        let inputs = circuit
+            .get_inputs()
+            .iter()
+            .zip(inputs)
+            .map(|(&id, &value)| (id, BigInt::from(value)))
+            .collect();
+        let values = circuit.simulate(&inputs, &bn254_prime()).unwrap();
+
+        circuit
+            .get_outputs()
+            .iter()
+            .map(|id| values[id].clone())
+            .collect()
+    }
+
+    #[test]
+    fn gate_ids_and_cycle_checks_follow_the_gates() {
+        let mut circuit = ArithmeticCircuit::new();
+        for id in 0..5 {
+            circuit.add_signal(id).unwrap();
+        }
+        assert_eq!(circuit.add_gate(AGateType::AAdd, 0, Some(1), 2).unwrap(), 0);
+        assert_eq!(circuit.add_gate(AGateType::AMul, 2, Some(2), 3).unwrap(), 1);
+
+        assert!(matches!(
+            circuit.add_gate(AGateType::ASub, 3, Some(1), 0),
+            Err(CircuitError::CircuitCycleDetected { gate_id: 2 })
+        ));
+        assert!(matches!(
+            circuit.add_connection(0, 3),
+            Err(CircuitError::CircuitCycleDetected { gate_id: 1 })
+        ));
+        assert_eq!(circuit.add_gate(AGateType::AAdd, 1, Some(3), 4).unwrap(), 2);
+
+        // The deserialized circuit rebuilds the index and continues the ids
+        let mut circuit = ArithmeticCircuit::from_json(&circuit.to_json()).unwrap();
+        assert!(matches!(
+            circuit.add_gate(AGateType::ASub, 4, Some(1), 0),
+            Err(CircuitError::CircuitCycleDetected { gate_id: 3 })
+        ));
+        circuit.add_signal(5).unwrap();
+        assert_eq!(circuit.add_gate(AGateType::AMul, 4, Some(4), 5).unwrap(), 3);
+        assert!(!circuit.has_cycle());
+    }
+
+    #[test]
+    fn fold_constants_folds_constant_chains_only() {
+        // a + (1 + 2) * (1 + 2)
+        let build = || {
+            let mut builder = CircuitBuilder::new();
+            let a = builder.input().unwrap();
+            let one = builder.constant(BigInt::one()).unwrap();
+            let two = builder.constant(BigInt::from(2)).unwrap();
+            let three = builder.add(one, two).unwrap();
+            let nine = builder.mul(three, three).unwrap();
+            let out = builder.add(a, nine).unwrap();
+            builder.output(out).unwrap();
+            builder.build()
+        };
+        let original = build();
+        let mut circuit = build();
+
+        assert_eq!(circuit.fold_constants(&bn254_prime()).unwrap(), 2);
+        assert_eq!(circuit.gate_count(), 1);
+        let gate = &circuit.get_gates()[0];
+        assert_eq!(gate.get_gate_type(), &AGateType::AAdd);
+        assert_eq!(
+            gate.get_lh_input(),
+            circuit
+                .get_signal_node(circuit.get_inputs()[0])
+                .unwrap()
+                .get_id()
+        );
+        assert_eq!(evaluate(&circuit, &[5]), vec![BigInt::from(14)]);
+        assert!(circuit.probabilistically_equivalent(&original, 10, &bn254_prime()));
+    }
+
+    #[test]
+    fn eliminate_dead_signals_keeps_the_live_path() {
+        // a + b, with a dangling a * b
+        let build = || {
+            let mut builder = CircuitBuilder::new();
+            let (a, b) = (builder.input().unwrap(), builder.input().unwrap());
+            builder.mul(a, b).unwrap();
+            let out = builder.add(a, b).unwrap();
+            builder.output(out).unwrap();
+            builder.build()
+        };
+        let original = build();
+        let mut circuit = build();
+
+        assert_eq!(circuit.eliminate_dead_signals(), (1, 1));
+        assert_eq!(circuit.gate_count(), 1);
+        assert_eq!(circuit.get_gates()[0].get_gate_type(), &AGateType::AAdd);
+        assert_eq!(evaluate(&circuit, &[2, 3]), vec![BigInt::from(5)]);
+        assert!(circuit.probabilistically_equivalent(&original, 10, &bn254_prime()));
+    }
+
+    #[test]
+    fn to_json_lists_the_circuit() {
+        let circuit = multiply_add();
+        let json = circuit.to_json();
+
+        assert_eq!(json["version"], json!(CIRCUIT_JSON_VERSION));
+        assert_eq!(json["signals"].as_array().unwrap().len(), 4);
+        assert_eq!(json["constants"].as_object().unwrap().len(), 1);
+        assert!(json["constants"]
+            .as_object()
+            .unwrap()
+            .values()
+            .all(|value| value == "3"));
+        let gate_types: Vec<&Value> = json["gates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|gate| &gate["type"])
+            .collect();
+        assert_eq!(gate_types, [&json!("AMul"), &json!("AAdd")]);
+        assert_eq!(json["inputs"], json!(circuit.get_inputs()));
+        assert_eq!(json["outputs"], json!(circuit.get_outputs()));
+    }
+
+    #[test]
+    fn from_json_round_trips() {
+        let circuit = multiply_add();
+        let json = circuit.to_json();
+        let parsed = ArithmeticCircuit::from_json(&json).unwrap();
+
+        assert_eq!(parsed.to_json(), json);
+        assert!(parsed.is_isomorphic_to(&circuit));
+        assert_eq!(evaluate(&parsed, &[4, 5]), vec![BigInt::from(23)]);
+
+        let mut unsupported = json.clone();
+        unsupported["version"] = json!(CIRCUIT_JSON_VERSION + 1);
+        assert!(matches!(
+            ArithmeticCircuit::from_json(&unsupported),
+            Err(CircuitError::InvalidCircuitJson(_))
+        ));
+
+        let mut dangling = json;
+        dangling["gates"][0]["output"] = json!(u32::MAX);
+        assert!(matches!(
+            ArithmeticCircuit::from_json(&dangling),
+            Err(CircuitError::InvalidCircuitJson(_))
+        ));
+    }
+
+    #[test]
+    fn stats_count_signals_gates_and_connections() {
+        let mut circuit = multiply_add();
+        let alias = u32::MAX;
+        circuit.add_signal(alias).unwrap();
+        circuit
+            .add_connection(circuit.get_outputs()[0], alias)
+            .unwrap();
+
+        assert_eq!(
+            circuit.stats(),
+            CircuitStats {
+                signal_count: 5,
+                constant_count: 1,
+                gate_count: 2,
+                gates_by_type: HashMap::from([(AGateType::AMul, 1), (AGateType::AAdd, 1)]),
+                connection_count: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn custom_gates_are_checked_and_serialized() {
+        let mut circuit = ArithmeticCircuit::new();
+        for id in 0..4 {
+            circuit.add_signal(id).unwrap();
+        }
+        circuit.register_custom_gate("lookup", 3).unwrap();
+
+        assert!(matches!(
+            circuit.register_custom_gate("lookup", 2),
+            Err(CircuitError::CustomGateAlreadyRegistered(_))
+        ));
+        assert!(matches!(
+            circuit.add_custom_gate("range", &[0], 3),
+            Err(CircuitError::CustomGateNotRegistered(_))
+        ));
+        assert!(matches!(
+            circuit.add_custom_gate("lookup", &[0, 1], 3),
+            Err(CircuitError::CustomGateArityMismatch(_))
+        ));
+        circuit.add_custom_gate("lookup", &[0, 1, 2], 3).unwrap();
+
+        let json = circuit.to_json();
+        assert_eq!(json["custom_gate_types"], json!({ "lookup": 3 }));
+        let parsed = ArithmeticCircuit::from_json(&json).unwrap();
+        let gate = &parsed.get_custom_gates()[0];
+        assert_eq!(gate.get_name(), "lookup");
+        assert_eq!(gate.get_inputs().len(), 3);
+        assert_eq!(parsed.to_json(), json);
+    }
+
+    #[test]
+    fn bit_decomposition_constrains_the_bits() {
+        let mut circuit = ArithmeticCircuit::new();
+        let five = circuit.add_const(BigInt::from(5)).unwrap();
+        let bits = circuit.add_bit_decomposition(five, 3).unwrap();
+        assert_eq!(bits.len(), 3);
+
+        // The bits are advice values, checked by the booleanity and recomposition constraints
+        let simulate = |values: [i64; 3]| {
+            let inputs = bits
+                .iter()
+                .zip(values)
+                .map(|(&bit, value)| (bit, BigInt::from(value)))
+                .collect();
+            circuit.simulate(&inputs, &bn254_prime())
+        };
+        let values = simulate([1, 0, 1]).unwrap();
+        assert_eq!(
+            bits.iter()
+                .map(|bit| values[bit].clone())
+                .collect::<Vec<_>>(),
+            [BigInt::one(), BigInt::zero(), BigInt::one()]
+        );
+        assert!(matches!(
+            simulate([0, 1, 1]),
+            Err(CircuitError::ConstraintNotSatisfied(_))
+        ));
+        assert!(matches!(
+            simulate([3, 1, 0]),
+            Err(CircuitError::ConstraintNotSatisfied(_))
+        ));
+    }
+
+    #[test]
+    fn iterators_borrow_the_circuit() {
+        let circuit = multiply_add();
+
+        assert!(circuit
+            .gates_iter()
+            .zip(circuit.get_gates())
+            .all(|(streamed, stored)| std::ptr::eq(streamed, stored)));
+        assert_eq!(circuit.gates_iter().count(), circuit.get_gates().len());
+
+        let mut constants: Vec<&BigInt> = circuit
+            .signals_iter()
+            .filter_map(|(_, value)| value)
+            .collect();
+        constants.sort();
+        assert_eq!(constants, [&BigInt::from(3)]);
+        assert_eq!(circuit.signals_iter().count(), 5);
+    }
+
+    #[test]
+    fn add_gate_checks_the_inputs() {
+        let mut circuit = ArithmeticCircuit::new();
+        for id in 0..3 {
+            circuit.add_signal(id).unwrap();
+        }
+
+        assert!(matches!(
+            circuit.add_gate(AGateType::AAdd, 0, None, 2),
+            Err(CircuitError::InvalidGateInputs(_))
+        ));
+        assert!(matches!(
+            circuit.add_gate(AGateType::ANeg, 0, Some(1), 2),
+            Err(CircuitError::InvalidGateInputs(_))
+        ));
+        assert!(matches!(
+            circuit.add_gate(AGateType::AAdd, 0, Some(3), 2),
+            Err(CircuitError::VariableNotDeclared)
+        ));
+        assert_eq!(circuit.gate_count(), 0);
+
+        circuit.add_gate(AGateType::ANeg, 0, None, 2).unwrap();
+        assert_eq!(
+            circuit.get_gates()[0].get_inputs(),
+            [circuit.get_signal_node(0).unwrap().get_id()]
+        );
+    }
+
+    #[test]
+    fn optimized_circuits_are_equivalent() {
+        let source = "pragma circom 2.1.6;
+            template T() {
+                signal input a;
+                signal input b;
+                signal output out;
+                signal unused;
+                signal x;
+                signal y;
+                unused <== a * a;
+                x <== (a + b) * (2 + 3);
+                y <== (a + b) * (2 + 3);
+                out <== x * y + b;
+            }";
+        let compile = |optimize: bool| {
+            let config = BuildConfig {
+                enable_constant_folding: optimize,
+                enable_cse: optimize,
+                enable_dead_signal_elimination: optimize,
+                ..BuildConfig::default()
+            };
+            compile_circom_source_with_config(source, "T()", config).unwrap()
+        };
+        let (original, optimized) = (compile(false), compile(true));
+
+        assert!(optimized.gate_count() < original.gate_count());
+        assert!(optimized.probabilistically_equivalent(&original, 10, &bn254_prime()));
+        let mut rng = thread_rng();
+        for _ in 0..10 {
+            let inputs = [rng.gen::<u32>() as i64, rng.gen::<u32>() as i64];
+            assert_eq!(evaluate(&optimized, &inputs), evaluate(&original, &inputs));
+        }
+
+        assert!(!multiply_add().probabilistically_equivalent(&original, 10, &bn254_prime()));
+    }
+
+    #[test]
+    fn equivalence_is_checked_in_the_given_field() {
+        let scale = |factor: i64| {
+            let mut builder = CircuitBuilder::new();
+            let a = builder.input().unwrap();
+            let factor = builder.constant(BigInt::from(factor)).unwrap();
+            let out = builder.mul(a, factor).unwrap();
+            builder.output(out).unwrap();
+            builder.build()
+        };
+
+        // Multiplying by 17 is multiplying by 0 in the field of order 17 only
+        assert!(scale(17).probabilistically_equivalent(&scale(0), 10, &BigInt::from(17)));
+        assert!(!scale(17).probabilistically_equivalent(&scale(0), 10, &bn254_prime()));
     }
 }
@@ -0,0 +1,93 @@
+//! # Compiler Module
+//!
+//! This module compiles circom programs given as source code, without an `Input` read from the command line.
+
+use crate::{
+    circom::parser::{parse_file, parse_sources},
+    circuit::ArithmeticCircuit,
+    program::{build_circuit_from_archive, BuildConfig, ProgramError},
+    runtime::generate_u32,
+};
+use std::{collections::HashMap, env, fs};
+
+/// Compiles a circom program with the default configuration, returning its arithmetic circuit.
+/// `main_component` is the expression instantiating the main component, like `Multiplier(2)`, which is declared
+/// after the source. It can be empty if the source already declares its main component.
+pub fn compile_circom_source(
+    source: &str,
+    main_component: &str,
+) -> Result<ArithmeticCircuit, ProgramError> {
+    compile_circom_source_with_config(source, main_component, BuildConfig::default())
+}
+
+/// Compiles a circom program with the given configuration, returning its arithmetic circuit.
+/// The circom parser reads programs from files, so the source is written to a temporary file, removed once parsed.
+/// Includes are resolved relative to the temporary directory.
+pub fn compile_circom_source_with_config(
+    source: &str,
+    main_component: &str,
+    config: BuildConfig,
+) -> Result<ArithmeticCircuit, ProgramError> {
+    let mut source = source.to_string();
+    if !main_component.is_empty() {
+        source.push_str(&format!("\ncomponent main = {};\n", main_component));
+    }
+
+    let path = env::temp_dir().join(format!("circuit_{}.circom", generate_u32()));
+    fs::write(&path, source)?;
+    let program_archive = parse_file(path.to_string_lossy().to_string(), Vec::new());
+    fs::remove_file(&path)?;
+
+    let program_archive = program_archive.map_err(|_| ProgramError::ParsingError)?;
+    build_circuit_from_archive(program_archive, config)
+}
+
+/// Compiles a circom program from in-memory sources with the given configuration, returning its arithmetic circuit.
+/// `files` maps paths to sources, and is used instead of the filesystem to resolve the includes of `main_file`,
+/// which must declare the main component. This is the only way to compile programs where there's no filesystem,
+/// like in WebAssembly modules.
+pub fn compile_circom_files(
+    files: &HashMap<String, String>,
+    main_file: &str,
+    config: BuildConfig,
+) -> Result<ArithmeticCircuit, ProgramError> {
+    let program_archive =
+        parse_sources(files, main_file).map_err(|_| ProgramError::ParsingError)?;
+    build_circuit_from_archive(program_archive, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiles_in_memory_files_with_includes() {
+        let files = HashMap::from([
+            (
+                "lib/add.circom".to_string(),
+                "pragma circom 2.1.6;
+                // Adds two signals
+                template Add() {
+                    signal input a;
+                    signal input b;
+                    signal output out;
+                    out <== a + b;
+                }"
+                .to_string(),
+            ),
+            (
+                "main.circom".to_string(),
+                "pragma circom 2.1.6;
+                include \"lib/add.circom\";
+                /* The main component */
+                component main = Add();"
+                    .to_string(),
+            ),
+        ]);
+        let circuit = compile_circom_files(&files, "main.circom", BuildConfig::default()).unwrap();
+
+        assert_eq!(circuit.get_inputs().len(), 2);
+        assert_eq!(circuit.get_outputs().len(), 1);
+        assert_eq!(circuit.gate_count(), 1);
+    }
+}
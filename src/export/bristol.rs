@@ -0,0 +1,127 @@
+//! # Bristol Module
+//!
+//! This module converts the arithmetic circuit into the Bristol Fashion gate-list format used by MPC backends.
+
+use crate::circuit::{AGateType, ArithmeticCircuit, CircuitError, Node};
+use crate::export::{input_position, sort_gates};
+use std::collections::{HashMap, HashSet};
+
+/// Returns the Bristol mnemonic of a gate type. `INV` is the field inverse rather than the boolean negation.
+/// Multiplications by a constant are plain `MUL` gates. `ANone` gates don't compute any operation and can't be exported.
+pub fn gate_mnemonic(gate_type: &AGateType) -> Result<&'static str, CircuitError> {
+    match gate_type {
+        AGateType::AAdd => Ok("ADD"),
+        AGateType::ACMul => Ok("MUL"),
+        AGateType::ADiv => Ok("DIV"),
+        AGateType::AEq => Ok("EQ"),
+        AGateType::AGEq => Ok("GEQ"),
+        AGateType::AGt => Ok("GT"),
+        AGateType::AInv => Ok("INV"),
+        AGateType::ALEq => Ok("LEQ"),
+        AGateType::ALt => Ok("LT"),
+        AGateType::AMul => Ok("MUL"),
+        AGateType::ANeg => Ok("NEG"),
+        AGateType::ANeq => Ok("NEQ"),
+        AGateType::ASub => Ok("SUB"),
+        AGateType::ANone => Err(CircuitError::UnsupportedGateType(format!(
+            "{:?} in Bristol format",
+            gate_type
+        ))),
+    }
+}
+
+/// Writes a circuit in the Bristol Fashion format:
+/// - A header line with the number of gates and wires.
+/// - A line with the number of input values followed by the number of wires of each of them.
+/// - A line with the number of output values followed by the number of wires of each of them.
+/// - One line per gate, in topological order, with its number of inputs and outputs, its wires and its mnemonic.
+///   Unary gates have a single input wire.
+///
+/// Each circuit node is a wire. Inputs come first, in the order they're marked, followed by the constant nodes, which form a second input value
+/// that must be provided by the evaluator, then the intermediate wires and finally the outputs.
+/// Gates without a Bristol mnemonic return an `UnsupportedGateType` error.
+pub fn to_bristol(circuit: &ArithmeticCircuit) -> Result<String, CircuitError> {
+    let gates = sort_gates(circuit)?;
+
+    let mut output_nodes = Vec::new();
+    for &signal in circuit.get_outputs() {
+        let node_id = circuit.get_signal_node(signal)?.get_id();
+        if !output_nodes.contains(&node_id) {
+            output_nodes.push(node_id);
+        }
+    }
+
+    let driven_nodes: HashSet<u32> = gates.iter().map(|gate| gate.get_output()).collect();
+    let (constant_nodes, mut input_nodes): (Vec<&Node>, Vec<&Node>) = circuit
+        .get_nodes()
+        .iter()
+        .filter(|node| !driven_nodes.contains(&node.get_id()))
+        .filter(|node| !output_nodes.contains(&node.get_id()))
+        .partition(|node| circuit.get_node_value(node).is_some());
+    input_nodes.sort_by_key(|node| input_position(circuit, node.get_id()));
+    let intermediate_nodes: Vec<u32> = gates
+        .iter()
+        .map(|gate| gate.get_output())
+        .filter(|node_id| !output_nodes.contains(node_id))
+        .collect();
+
+    let wires: HashMap<u32, usize> = input_nodes
+        .iter()
+        .chain(constant_nodes.iter())
+        .map(|node| node.get_id())
+        .chain(intermediate_nodes)
+        .chain(output_nodes.iter().copied())
+        .enumerate()
+        .map(|(wire, node_id)| (node_id, wire))
+        .collect();
+    let get_wire = |node_id: u32| {
+        wires
+            .get(&node_id)
+            .copied()
+            .ok_or(CircuitError::NodeNotFound)
+    };
+
+    let input_values = if constant_nodes.is_empty() {
+        format!("1 {}", input_nodes.len())
+    } else {
+        format!("2 {} {}", input_nodes.len(), constant_nodes.len())
+    };
+    let mut bristol = format!(
+        "{} {}\n{}\n1 {}\n\n",
+        gates.len(),
+        wires.len(),
+        input_values,
+        output_nodes.len()
+    );
+
+    for gate in gates {
+        let inputs = gate
+            .get_inputs()
+            .into_iter()
+            .map(|input| Ok(get_wire(input)?.to_string()))
+            .collect::<Result<Vec<String>, CircuitError>>()?;
+        bristol.push_str(&format!(
+            "{} 1 {} {} {}\n",
+            inputs.len(),
+            inputs.join(" "),
+            get_wire(gate.get_output())?,
+            gate_mnemonic(gate.get_gate_type())?
+        ));
+    }
+
+    Ok(bristol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::test_utils::multiply_add;
+
+    #[test]
+    fn writes_inputs_constants_and_gates() {
+        assert_eq!(
+            to_bristol(&multiply_add()).unwrap(),
+            "2 5\n2 2 1\n1 1\n\n2 1 0 1 3 MUL\n2 1 3 2 4 ADD\n"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! # DOT Module
+//!
+//! This module converts the arithmetic circuit into a Graphviz DOT graph, to visualize it with tools like `dot -Tsvg`.
+
+use crate::circuit::ArithmeticCircuit;
+use std::collections::{HashMap, HashSet};
+
+/// Writes a circuit as a DOT graph.
+/// Signals are drawn as ellipses labeled with their name, or id if unnamed, colored by kind: inputs (not produced by any gate) in blue and
+/// outputs in green. Constants are drawn as grey diamonds, along with their value. Gates are drawn as boxes labeled
+/// with their type, and custom gates with their name.
+/// Connected signals are linked by dashed edges, and gates by edges from their inputs and to their output.
+pub fn to_dot(circuit: &ArithmeticCircuit) -> String {
+    let outputs: HashSet<u32> = circuit.get_outputs().iter().copied().collect();
+    let driven_nodes: HashSet<u32> = circuit
+        .gates_iter()
+        .map(|gate| gate.get_output())
+        .chain(
+            circuit
+                .get_custom_gates()
+                .iter()
+                .map(|gate| gate.get_output()),
+        )
+        .collect();
+
+    let mut dot = String::from("digraph circuit {\n");
+
+    // Signals, grouped by node
+    let mut node_signals: HashMap<u32, u32> = HashMap::new();
+    for node in circuit.get_nodes() {
+        let signals = node.get_signals();
+        let value = circuit.get_node_value(node);
+
+        for &signal in &signals {
+            let name = circuit.signal_label(signal);
+            let (label, color) = if let Some(value) = &value {
+                (format!("{} = {}", name, value), Some("lightgrey"))
+            } else if outputs.contains(&signal) {
+                (name, Some("lightgreen"))
+            } else if !driven_nodes.contains(&node.get_id()) {
+                (name, Some("lightblue"))
+            } else {
+                (name, None)
+            };
+            let shape = if value.is_some() {
+                "diamond"
+            } else {
+                "ellipse"
+            };
+
+            match color {
+                Some(color) => dot.push_str(&format!(
+                    "  s{} [label=\"{}\", shape={}, style=filled, fillcolor={}];\n",
+                    signal, label, shape, color
+                )),
+                None => dot.push_str(&format!(
+                    "  s{} [label=\"{}\", shape={}];\n",
+                    signal, label, shape
+                )),
+            }
+        }
+
+        // Connections between the signals of the node
+        if let Some((&first, rest)) = signals.split_first() {
+            for signal in rest {
+                dot.push_str(&format!("  s{} -> s{} [style=dashed];\n", first, signal));
+            }
+            node_signals.insert(node.get_id(), first);
+        }
+    }
+
+    // Gates, linked through the first signal of their nodes
+    for (index, gate) in circuit.gates_iter().enumerate() {
+        dot.push_str(&format!(
+            "  g{} [shape=box, label=\"{:?}\"];\n",
+            index,
+            gate.get_gate_type()
+        ));
+
+        if let Some(signal) = node_signals.get(&gate.get_lh_input()) {
+            dot.push_str(&format!("  s{} -> g{} [label=\"lh\"];\n", signal, index));
+        }
+        if let Some(signal) = gate
+            .get_rh_input()
+            .and_then(|rh_input| node_signals.get(&rh_input))
+        {
+            dot.push_str(&format!("  s{} -> g{} [label=\"rh\"];\n", signal, index));
+        }
+        if let Some(signal) = node_signals.get(&gate.get_output()) {
+            dot.push_str(&format!("  g{} -> s{};\n", index, signal));
+        }
+    }
+
+    for (index, gate) in circuit.get_custom_gates().iter().enumerate() {
+        dot.push_str(&format!(
+            "  c{} [shape=box, style=rounded, label=\"{}\"];\n",
+            index,
+            gate.get_name()
+        ));
+
+        for (position, input) in gate.get_inputs().iter().enumerate() {
+            if let Some(signal) = node_signals.get(input) {
+                dot.push_str(&format!(
+                    "  s{} -> c{} [label=\"{}\"];\n",
+                    signal, index, position
+                ));
+            }
+        }
+        if let Some(signal) = node_signals.get(&gate.get_output()) {
+            dot.push_str(&format!("  c{} -> s{};\n", index, signal));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::test_utils::multiply_add_with_signals;
+
+    #[test]
+    fn draws_signals_constants_and_gates() {
+        let (circuit, signals) = multiply_add_with_signals();
+        let dot = to_dot(&circuit);
+
+        let [a, b, product, three, out] = signals.map(|signal| signal.id());
+        let signal = |id: u32, value: &str, shape: &str| {
+            format!(
+                "  s{} [label=\"signal_{}{}\", shape={}];",
+                id, id, value, shape
+            )
+        };
+        for line in [
+            signal(a, "", "ellipse, style=filled, fillcolor=lightblue"),
+            signal(b, "", "ellipse, style=filled, fillcolor=lightblue"),
+            signal(product, "", "ellipse"),
+            signal(three, " = 3", "diamond, style=filled, fillcolor=lightgrey"),
+            signal(out, "", "ellipse, style=filled, fillcolor=lightgreen"),
+            "  g0 [shape=box, label=\"AMul\"];".to_string(),
+            format!("  s{} -> g0 [label=\"lh\"];", a),
+            format!("  s{} -> g0 [label=\"rh\"];", b),
+            format!("  g0 -> s{};", product),
+            "  g1 [shape=box, label=\"AAdd\"];".to_string(),
+            format!("  s{} -> g1 [label=\"lh\"];", product),
+            format!("  s{} -> g1 [label=\"rh\"];", three),
+            format!("  g1 -> s{};", out),
+        ] {
+            assert!(
+                dot.lines().any(|dot_line| dot_line == line),
+                "missing {}",
+                line
+            );
+        }
+        assert!(dot.starts_with("digraph circuit {\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
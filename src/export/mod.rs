@@ -0,0 +1,46 @@
+//! # Export Module
+//!
+//! This module converts the arithmetic circuit into formats consumed by external tools.
+
+pub mod bristol;
+pub mod dot;
+pub mod plonk;
+pub mod r1cs;
+
+use crate::circuit::{ArithmeticCircuit, ArithmeticGate, CircuitError, GateId};
+use std::collections::HashMap;
+
+/// Returns the gates of a circuit in topological order.
+/// Custom gates have no meaning for the export formats, so circuits containing them are rejected.
+fn sort_gates(circuit: &ArithmeticCircuit) -> Result<Vec<&ArithmeticGate>, CircuitError> {
+    if let Some(gate) = circuit.get_custom_gates().first() {
+        return Err(CircuitError::UnsupportedGateType(format!(
+            "custom gate {}",
+            gate.get_name()
+        )));
+    }
+
+    let gates: HashMap<GateId, &ArithmeticGate> = circuit
+        .gates_iter()
+        .map(|gate| (gate.get_id(), gate))
+        .collect();
+
+    circuit
+        .topological_sort()?
+        .iter()
+        .map(|id| gates.get(id).copied().ok_or(CircuitError::GateNotFound))
+        .collect()
+}
+
+/// Returns the position of the first marked input in a node, placing nodes without any marked input last.
+fn input_position(circuit: &ArithmeticCircuit, node_id: u32) -> usize {
+    circuit
+        .get_inputs()
+        .iter()
+        .position(|&signal| {
+            circuit
+                .get_signal_node(signal)
+                .is_ok_and(|node| node.get_id() == node_id)
+        })
+        .unwrap_or(usize::MAX)
+}
@@ -0,0 +1,293 @@
+//! # PLONK Module
+//!
+//! This module converts the arithmetic circuit into PLONK gates, each constraining three wires with selectors.
+
+use crate::circuit::{AGateType, ArithmeticCircuit, ArithmeticGate, CircuitError};
+use crate::export::sort_gates;
+use crate::process::modulo;
+use circom_circom_algebra::num_traits::{One, Zero};
+use num_bigint_dig::BigInt;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Selectors of a PLONK gate, constraining its wires with `ql * a + qr * b + qm * a * b + qo * c + qc = 0`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlonkSelectors {
+    pub ql: BigInt,
+    pub qr: BigInt,
+    pub qm: BigInt,
+    pub qo: BigInt,
+    pub qc: BigInt,
+}
+
+impl PlonkSelectors {
+    /// Creates selectors from small signed coefficients, negative ones standing for their field opposite.
+    pub fn new(ql: i32, qr: i32, qm: i32, qo: i32, qc: i32) -> Self {
+        Self {
+            ql: BigInt::from(ql),
+            qr: BigInt::from(qr),
+            qm: BigInt::from(qm),
+            qo: BigInt::from(qo),
+            qc: BigInt::from(qc),
+        }
+    }
+}
+
+/// PLONK gate, with its selectors reduced in the field and the node ids of its wires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlonkGate {
+    pub ql: BigInt,
+    pub qr: BigInt,
+    pub qm: BigInt,
+    pub qo: BigInt,
+    pub qc: BigInt,
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+}
+
+impl PlonkGate {
+    /// Creates a gate on the given wires, reducing the selectors in the field.
+    fn new(selectors: &PlonkSelectors, wires: (u32, u32, u32), prime: &BigInt) -> Self {
+        Self {
+            ql: modulo(&selectors.ql, prime),
+            qr: modulo(&selectors.qr, prime),
+            qm: modulo(&selectors.qm, prime),
+            qo: modulo(&selectors.qo, prime),
+            qc: modulo(&selectors.qc, prime),
+            a: wires.0,
+            b: wires.1,
+            c: wires.2,
+        }
+    }
+
+    /// Returns the JSON representation of the gate, with the selectors as decimal strings.
+    fn to_json(&self) -> Value {
+        json!({
+            "ql": self.ql.to_string(),
+            "qr": self.qr.to_string(),
+            "qm": self.qm.to_string(),
+            "qo": self.qo.to_string(),
+            "qc": self.qc.to_string(),
+            "a": self.a,
+            "b": self.b,
+            "c": self.c,
+        })
+    }
+}
+
+/// Selectors of the gate types exported with their left input, right input and output as the `a`, `b` and `c` wires.
+/// Unary gates use their input as the unused `b` wire.
+#[derive(Clone, Debug)]
+pub struct CustomGateRegistry {
+    selectors: HashMap<AGateType, PlonkSelectors>,
+}
+
+impl Default for CustomGateRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            selectors: HashMap::new(),
+        };
+        registry.register(AGateType::AAdd, PlonkSelectors::new(1, 1, 0, -1, 0));
+        registry.register(AGateType::ACMul, PlonkSelectors::new(0, 0, 1, -1, 0));
+        registry.register(AGateType::AMul, PlonkSelectors::new(0, 0, 1, -1, 0));
+        registry.register(AGateType::ANeg, PlonkSelectors::new(1, 0, 0, 1, 0));
+        registry.register(AGateType::ASub, PlonkSelectors::new(1, -1, 0, -1, 0));
+        registry
+    }
+}
+
+impl CustomGateRegistry {
+    /// Creates a registry with the selectors of additions, subtractions, multiplications and negations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the selectors of a gate type, replacing the previous ones if any.
+    pub fn register(&mut self, gate_type: AGateType, selectors: PlonkSelectors) {
+        self.selectors.insert(gate_type, selectors);
+    }
+
+    /// Returns the selectors of a gate type, if registered.
+    pub fn get(&self, gate_type: &AGateType) -> Option<&PlonkSelectors> {
+        self.selectors.get(gate_type)
+    }
+}
+
+/// Converts a circuit into PLONK gates, using nodes as wires:
+/// - Each constant node is fixed to its value by a gate `a - value = 0`.
+/// - Each gate of a registered type is a gate with its selectors, in topological order.
+/// - Divisions `c = a / b` are the products `c * b = a`, and inverses `c = 1 / a` the products `a * c = 1`.
+/// - Each equality constraint `lhs === rhs` is a gate `a - b = 0`.
+///
+/// Witness hints are left unconstrained, so no gate references their outputs: these wires must be supplied with the
+/// witness, like inputs, and are listed by `to_plonk_json`. Other gate types return an `UnsupportedGateType` error, so comparisons must
+/// be lowered first with `ArithmeticCircuit::lower_comparisons`.
+pub fn to_plonk_gates(
+    circuit: &ArithmeticCircuit,
+    prime: &BigInt,
+    registry: &CustomGateRegistry,
+) -> Result<Vec<PlonkGate>, CircuitError> {
+    let mut plonk_gates = Vec::new();
+
+    for node in circuit.get_nodes() {
+        if let Some(value) = circuit.get_node_value(node) {
+            let selectors = PlonkSelectors {
+                ql: BigInt::one(),
+                qr: BigInt::zero(),
+                qm: BigInt::zero(),
+                qo: BigInt::zero(),
+                qc: -value,
+            };
+            let wire = node.get_id();
+            plonk_gates.push(PlonkGate::new(&selectors, (wire, wire, wire), prime));
+        }
+    }
+
+    for gate in sort_gates(circuit)? {
+        if circuit.is_hint_gate(gate.get_id()) {
+            continue;
+        }
+        plonk_gates.push(gate_to_plonk(gate, prime, registry)?);
+    }
+
+    for &(lhs, rhs) in circuit.get_constraints() {
+        let lhs = circuit.get_signal_node(lhs)?.get_id();
+        let rhs = circuit.get_signal_node(rhs)?.get_id();
+        plonk_gates.push(PlonkGate::new(
+            &PlonkSelectors::new(1, -1, 0, 0, 0),
+            (lhs, rhs, lhs),
+            prime,
+        ));
+    }
+
+    Ok(plonk_gates)
+}
+
+/// Converts a single arithmetic gate into a PLONK gate.
+fn gate_to_plonk(
+    gate: &ArithmeticGate,
+    prime: &BigInt,
+    registry: &CustomGateRegistry,
+) -> Result<PlonkGate, CircuitError> {
+    let lhs = gate.get_lh_input();
+    let output = gate.get_output();
+
+    if let Some(selectors) = registry.get(gate.get_gate_type()) {
+        let rhs = gate.get_rh_input().unwrap_or(lhs);
+        return Ok(PlonkGate::new(selectors, (lhs, rhs, output), prime));
+    }
+
+    match (gate.get_gate_type(), gate.get_rh_input()) {
+        (AGateType::ADiv, Some(rhs)) => Ok(PlonkGate::new(
+            &PlonkSelectors::new(0, 0, 1, -1, 0),
+            (output, rhs, lhs),
+            prime,
+        )),
+        (AGateType::AInv, _) => Ok(PlonkGate::new(
+            &PlonkSelectors::new(0, 0, 1, 0, -1),
+            (lhs, output, output),
+            prime,
+        )),
+        (gate_type, _) => Err(CircuitError::UnsupportedGateType(format!(
+            "{:?} in PLONK",
+            gate_type
+        ))),
+    }
+}
+
+/// Returns the JSON representation of the PLONK gates of a circuit, along with the wires of its inputs and outputs,
+/// and the `hints` wires, outputs of witness hints, which aren't computed by any gate and must be given with the inputs.
+pub fn to_plonk_json(
+    circuit: &ArithmeticCircuit,
+    prime: &BigInt,
+    registry: &CustomGateRegistry,
+) -> Result<Value, CircuitError> {
+    let gates: Vec<Value> = to_plonk_gates(circuit, prime, registry)?
+        .iter()
+        .map(PlonkGate::to_json)
+        .collect();
+    let inputs = circuit
+        .get_inputs()
+        .iter()
+        .map(|&signal| Ok(circuit.get_signal_node(signal)?.get_id()))
+        .collect::<Result<Vec<u32>, CircuitError>>()?;
+    let outputs = circuit
+        .get_outputs()
+        .iter()
+        .map(|&signal| Ok(circuit.get_signal_node(signal)?.get_id()))
+        .collect::<Result<Vec<u32>, CircuitError>>()?;
+    let mut hints: Vec<u32> = circuit
+        .gates_iter()
+        .filter(|gate| circuit.is_hint_gate(gate.get_id()))
+        .map(|gate| gate.get_output())
+        .collect();
+    hints.sort_unstable();
+    hints.dedup();
+
+    Ok(json!({
+        "gates": gates,
+        "inputs": inputs,
+        "outputs": outputs,
+        "hints": hints,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::{CircuitBuilder, SignalHandle},
+        circuit::test_utils::bn254_prime,
+        compiler::compile_circom_source,
+    };
+
+    #[test]
+    fn gates_get_the_selectors_of_their_type() {
+        let mut builder = CircuitBuilder::new();
+        let (a, b) = (builder.input().unwrap(), builder.input().unwrap());
+        let sum = builder.add(a, b).unwrap();
+        let difference = builder.sub(a, b).unwrap();
+        let product = builder.mul(a, b).unwrap();
+        let opposite = builder.neg(a).unwrap();
+        let quotient = builder.div(a, b).unwrap();
+        let inverse = builder.inv(a).unwrap();
+        let circuit = builder.build();
+
+        let prime = bn254_prime();
+        let wire = |signal: SignalHandle| circuit.get_signal_node(signal.id()).unwrap().get_id();
+        let (a, b) = (wire(a), wire(b));
+        let expected = [
+            (PlonkSelectors::new(1, 1, 0, -1, 0), (a, b, wire(sum))),
+            (
+                PlonkSelectors::new(1, -1, 0, -1, 0),
+                (a, b, wire(difference)),
+            ),
+            (PlonkSelectors::new(0, 0, 1, -1, 0), (a, b, wire(product))),
+            (PlonkSelectors::new(1, 0, 0, 1, 0), (a, a, wire(opposite))),
+            (PlonkSelectors::new(0, 0, 1, -1, 0), (wire(quotient), b, a)),
+            (
+                PlonkSelectors::new(0, 0, 1, 0, -1),
+                (a, wire(inverse), wire(inverse)),
+            ),
+        ];
+
+        let gates = circuit.to_plonk_gates(&prime).unwrap();
+        assert_eq!(gates.len(), expected.len());
+        for (selectors, wires) in &expected {
+            assert!(gates.contains(&PlonkGate::new(selectors, *wires, &prime)));
+        }
+    }
+
+    #[test]
+    fn hint_wires_are_listed() {
+        let source = "pragma circom 2.1.6;\ntemplate T() {\nsignal input a; signal input b; signal output out; out <-- a * b;\n}";
+        let circuit = compile_circom_source(source, "T()").unwrap();
+        let out = circuit.public_outputs()["out"];
+        let out_wire = circuit.get_signal_node(out).unwrap().get_id();
+
+        let json = circuit.to_plonk_json(&bn254_prime()).unwrap();
+        assert_eq!(json["gates"].as_array().unwrap().len(), 0);
+        assert_eq!(json["hints"], json!([out_wire]));
+    }
+}
@@ -0,0 +1,395 @@
+//! # R1CS Module
+//!
+//! This module converts the arithmetic circuit into a Rank-1 Constraint System, which can be written in the binary
+//! `.r1cs` format used by snarkjs.
+
+use crate::circuit::{AGateType, ArithmeticCircuit, ArithmeticGate, CircuitError};
+use crate::export::{input_position, sort_gates};
+use crate::process::modulo;
+use circom_circom_algebra::num_traits::{One, Zero};
+use num_bigint_dig::BigInt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+
+/// Index of the wire holding the constant `1`.
+pub const ONE_WIRE: u32 = 0;
+
+/// Linear combination of wires, mapping each wire index to its coefficient.
+pub type LinearCombination = BTreeMap<u32, BigInt>;
+
+/// Constraint of the form `A * B = C`, where `A`, `B` and `C` are linear combinations of wires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct R1CSConstraint {
+    pub a: LinearCombination,
+    pub b: LinearCombination,
+    pub c: LinearCombination,
+}
+
+/// Rank-1 Constraint System of a circuit.
+/// Wires are ordered as expected by snarkjs: the constant wire, then the outputs, then the inputs and finally the
+/// internal wires.
+#[derive(Clone, Debug)]
+pub struct R1CSFile {
+    prime: BigInt,
+    n_wires: u32,
+    n_pub_out: u32,
+    n_pub_in: u32,
+    n_prv_in: u32,
+    constraints: Vec<R1CSConstraint>,
+}
+
+impl R1CSFile {
+    /// Builds the constraint system of a circuit.
+    /// Additions, subtractions and products or divisions by a constant are folded into linear combinations, so only
+    /// non-linear gates and outputs produce constraints.
+    pub fn from_circuit(circuit: &ArithmeticCircuit, prime: &BigInt) -> Result<Self, CircuitError> {
+        let mut builder = R1CSBuilder::new(circuit, prime);
+
+        // Allocate the output wires
+        for &signal in circuit.get_outputs() {
+            let node = circuit.get_signal_node(signal)?;
+            if circuit.get_node_value(&node).is_none()
+                && !builder.wires.contains_key(&node.get_id())
+            {
+                builder.allocate_wire(node.get_id());
+            }
+        }
+        let n_pub_out = builder.n_wires - 1;
+
+        // Allocate the input wires, which are the non constant nodes not driven by any gate, marked inputs first
+        let driven_nodes: HashSet<u32> =
+            circuit.gates_iter().map(|gate| gate.get_output()).collect();
+        let mut input_nodes: Vec<u32> = circuit
+            .get_nodes()
+            .iter()
+            .filter(|node| circuit.get_node_value(node).is_none())
+            .map(|node| node.get_id())
+            .filter(|node_id| !driven_nodes.contains(node_id))
+            .collect();
+        input_nodes.sort_by_key(|&node_id| input_position(circuit, node_id));
+        for node_id in input_nodes {
+            if !builder.wires.contains_key(&node_id) {
+                builder.allocate_wire(node_id);
+            }
+        }
+        let n_prv_in = builder.n_wires - 1 - n_pub_out;
+
+        for gate in sort_gates(circuit)? {
+            builder.add_gate(gate)?;
+        }
+
+        for &(lhs, rhs) in circuit.get_constraints() {
+            let lhs = builder.get_linear_combination(circuit.get_signal_node(lhs)?.get_id())?;
+            let rhs = builder.get_linear_combination(circuit.get_signal_node(rhs)?.get_id())?;
+            builder.constraints.push(R1CSConstraint {
+                a: lhs,
+                b: wire_linear_combination(ONE_WIRE),
+                c: rhs,
+            });
+        }
+
+        Ok(Self {
+            prime: prime.clone(),
+            n_wires: builder.n_wires,
+            n_pub_out,
+            n_pub_in: 0,
+            n_prv_in,
+            constraints: builder.constraints,
+        })
+    }
+
+    /// Returns the constraints of the system.
+    pub fn get_constraints(&self) -> &[R1CSConstraint] {
+        &self.constraints
+    }
+
+    /// Returns the number of wires, including the constant wire.
+    pub fn get_wire_count(&self) -> u32 {
+        self.n_wires
+    }
+
+    /// Writes the constraint system in the binary `.r1cs` format.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let field_size = ((self.prime.bits() + 63) / 64 * 8) as u32;
+
+        // Header section
+        let mut header = Vec::new();
+        header.extend(field_size.to_le_bytes());
+        header.extend(field_element_bytes(&self.prime, field_size));
+        header.extend(self.n_wires.to_le_bytes());
+        header.extend(self.n_pub_out.to_le_bytes());
+        header.extend(self.n_pub_in.to_le_bytes());
+        header.extend(self.n_prv_in.to_le_bytes());
+        header.extend((self.n_wires as u64).to_le_bytes());
+        header.extend((self.constraints.len() as u32).to_le_bytes());
+
+        // Constraints section
+        let mut constraints = Vec::new();
+        for constraint in &self.constraints {
+            for linear_combination in [&constraint.a, &constraint.b, &constraint.c] {
+                constraints.extend((linear_combination.len() as u32).to_le_bytes());
+                for (wire, coefficient) in linear_combination {
+                    constraints.extend(wire.to_le_bytes());
+                    constraints.extend(field_element_bytes(coefficient, field_size));
+                }
+            }
+        }
+
+        // Wire to label section, each wire is its own label
+        let labels: Vec<u8> = (0..self.n_wires as u64)
+            .flat_map(|label| label.to_le_bytes())
+            .collect();
+
+        writer.write_all(b"r1cs")?;
+        writer.write_all(&1u32.to_le_bytes())?;
+        writer.write_all(&3u32.to_le_bytes())?;
+        for (section_type, section) in [(1u32, header), (2, constraints), (3, labels)] {
+            writer.write_all(&section_type.to_le_bytes())?;
+            writer.write_all(&(section.len() as u64).to_le_bytes())?;
+            writer.write_all(&section)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps track of the wires and linear combinations while the constraint system is built.
+struct R1CSBuilder<'a> {
+    circuit: &'a ArithmeticCircuit,
+    prime: &'a BigInt,
+    n_wires: u32,
+    wires: HashMap<u32, u32>,
+    linear_combinations: HashMap<u32, LinearCombination>,
+    constraints: Vec<R1CSConstraint>,
+}
+
+impl<'a> R1CSBuilder<'a> {
+    fn new(circuit: &'a ArithmeticCircuit, prime: &'a BigInt) -> Self {
+        Self {
+            circuit,
+            prime,
+            n_wires: 1,
+            wires: HashMap::new(),
+            linear_combinations: HashMap::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    /// Allocates a new wire for a node.
+    fn allocate_wire(&mut self, node_id: u32) -> u32 {
+        let wire = self.n_wires;
+        self.wires.insert(node_id, wire);
+        self.n_wires += 1;
+        wire
+    }
+
+    /// Returns the linear combination of wires that holds the value of a node.
+    fn get_linear_combination(&self, node_id: u32) -> Result<LinearCombination, CircuitError> {
+        let node = self.circuit.get_node(node_id)?;
+
+        if let Some(value) = self.circuit.get_node_value(&node) {
+            return Ok(scale(
+                &wire_linear_combination(ONE_WIRE),
+                &value,
+                self.prime,
+            ));
+        }
+        if let Some(linear_combination) = self.linear_combinations.get(&node_id) {
+            return Ok(linear_combination.clone());
+        }
+        if let Some(&wire) = self.wires.get(&node_id) {
+            return Ok(wire_linear_combination(wire));
+        }
+
+        Err(CircuitError::NodeNotFound)
+    }
+
+    /// Adds a gate, either as a linear combination of its inputs or as a constraint on a new wire.
+    /// Witness hints only get a wire, as their computation isn't constrained.
+    fn add_gate(&mut self, gate: &ArithmeticGate) -> Result<(), CircuitError> {
+        if self.circuit.is_hint_gate(gate.get_id()) {
+            if !self.wires.contains_key(&gate.get_output()) {
+                self.allocate_wire(gate.get_output());
+            }
+            return Ok(());
+        }
+
+        let lhs = self.get_linear_combination(gate.get_lh_input())?;
+        let rhs = gate
+            .get_rh_input()
+            .map(|rh_input| self.get_linear_combination(rh_input))
+            .transpose()?;
+
+        // Unary gates are handled as their binary equivalent, `-x` as `0 - x` and `x^-1` as `1 / x`
+        let (gate_type, lhs, rhs) = match (gate.get_gate_type(), rhs) {
+            (AGateType::ANeg, _) => (&AGateType::ASub, LinearCombination::new(), lhs),
+            (AGateType::AInv, _) => (&AGateType::ADiv, wire_linear_combination(ONE_WIRE), lhs),
+            (gate_type, Some(rhs)) => (gate_type, lhs, rhs),
+            (gate_type, None) => {
+                return Err(CircuitError::InvalidGateInputs(format!(
+                    "{:?} without right-hand input",
+                    gate_type
+                )))
+            }
+        };
+
+        let linear = match gate_type {
+            AGateType::AAdd => Some(combine(&lhs, &rhs, &BigInt::one(), self.prime)),
+            AGateType::ASub => Some(combine(
+                &lhs,
+                &rhs,
+                &(self.prime - BigInt::one()),
+                self.prime,
+            )),
+            AGateType::ACMul | AGateType::AMul => match (get_constant(&lhs), get_constant(&rhs)) {
+                (Some(value), _) => Some(scale(&rhs, &value, self.prime)),
+                (_, Some(value)) => Some(scale(&lhs, &value, self.prime)),
+                _ => None,
+            },
+            AGateType::ADiv => get_constant(&rhs)
+                .filter(|value| !value.is_zero())
+                .map(|value| {
+                    let inverse = value.modpow(&(self.prime - BigInt::from(2u32)), self.prime);
+                    scale(&lhs, &inverse, self.prime)
+                }),
+            gate_type => {
+                return Err(CircuitError::UnsupportedGateType(format!(
+                    "{:?} in R1CS",
+                    gate_type
+                )))
+            }
+        };
+
+        let output_id = gate.get_output();
+        match linear {
+            Some(linear_combination) => match self.wires.get(&output_id) {
+                // Outputs have their own wire, so the combination is constrained to it
+                Some(&wire) => self.constraints.push(R1CSConstraint {
+                    a: linear_combination,
+                    b: wire_linear_combination(ONE_WIRE),
+                    c: wire_linear_combination(wire),
+                }),
+                None => {
+                    self.linear_combinations
+                        .insert(output_id, linear_combination);
+                }
+            },
+            None => {
+                let wire = match self.wires.get(&output_id) {
+                    Some(&wire) => wire,
+                    None => self.allocate_wire(output_id),
+                };
+
+                let constraint = match gate_type {
+                    AGateType::ADiv => R1CSConstraint {
+                        a: wire_linear_combination(wire),
+                        b: rhs,
+                        c: lhs,
+                    },
+                    _ => R1CSConstraint {
+                        a: lhs,
+                        b: rhs,
+                        c: wire_linear_combination(wire),
+                    },
+                };
+                self.constraints.push(constraint);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the linear combination holding a single wire.
+fn wire_linear_combination(wire: u32) -> LinearCombination {
+    LinearCombination::from([(wire, BigInt::one())])
+}
+
+/// Returns the value of a linear combination that only depends on the constant wire.
+fn get_constant(linear_combination: &LinearCombination) -> Option<BigInt> {
+    if linear_combination.keys().any(|&wire| wire != ONE_WIRE) {
+        return None;
+    }
+
+    Some(
+        linear_combination
+            .get(&ONE_WIRE)
+            .cloned()
+            .unwrap_or_else(BigInt::zero),
+    )
+}
+
+/// Computes `lhs + factor * rhs`.
+fn combine(
+    lhs: &LinearCombination,
+    rhs: &LinearCombination,
+    factor: &BigInt,
+    prime: &BigInt,
+) -> LinearCombination {
+    let mut result = lhs.clone();
+    for (wire, coefficient) in rhs {
+        let sum = result.get(wire).cloned().unwrap_or_else(BigInt::zero) + factor * coefficient;
+        result.insert(*wire, modulo(&sum, prime));
+    }
+
+    result.retain(|_, coefficient| !coefficient.is_zero());
+    result
+}
+
+/// Computes `factor * linear_combination`.
+fn scale(
+    linear_combination: &LinearCombination,
+    factor: &BigInt,
+    prime: &BigInt,
+) -> LinearCombination {
+    linear_combination
+        .iter()
+        .map(|(wire, coefficient)| (*wire, modulo(&(factor * coefficient), prime)))
+        .filter(|(_, coefficient)| !coefficient.is_zero())
+        .collect()
+}
+
+/// Encodes a field element as little endian bytes of the given size.
+fn field_element_bytes(value: &BigInt, size: u32) -> Vec<u8> {
+    let (_, mut bytes) = value.to_bytes_le();
+    bytes.resize(size as usize, 0);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{circuit::test_utils::bn254_prime, compiler::compile_circom_source};
+
+    /// Compiles the body of a template into a constraint system.
+    fn compile_r1cs(body: &str) -> R1CSFile {
+        let source = format!("pragma circom 2.1.6;\ntemplate T() {{\n{}\n}}", body);
+        let circuit = compile_circom_source(&source, "T()").unwrap();
+
+        R1CSFile::from_circuit(&circuit, &bn254_prime()).unwrap()
+    }
+
+    #[test]
+    fn hint_gates_are_not_constrained() {
+        let constrained =
+            compile_r1cs("signal input a; signal input b; signal output out; out <== a * b;");
+        let hint =
+            compile_r1cs("signal input a; signal input b; signal output out; out <-- a * b;");
+
+        assert_eq!(constrained.get_constraints().len(), 1);
+        assert_eq!(hint.get_constraints().len(), 0);
+        assert_eq!(constrained.get_wire_count(), hint.get_wire_count());
+    }
+
+    #[test]
+    fn gateless_hints_get_their_own_wire() {
+        let constrained = compile_r1cs("signal input a; signal output out; out <== a;");
+        let hint = compile_r1cs("signal input a; signal output out; out <-- a;");
+
+        // With `<==` both signals share a wire, with `<--` the output has its own unconstrained wire
+        assert_eq!(constrained.get_wire_count(), 2);
+        assert_eq!(hint.get_wire_count(), 3);
+        assert!(constrained.get_constraints().is_empty());
+        assert!(hint.get_constraints().is_empty());
+    }
+}
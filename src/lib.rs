@@ -2,8 +2,14 @@
 //!
 //! This library provides the functionality to convert a Circom program into an arithmetic circuit.
 
+pub mod builder;
+pub mod cache;
 pub mod circom;
 pub mod circuit;
+pub mod compiler;
+pub mod export;
 pub mod process;
 pub mod program;
 pub mod runtime;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
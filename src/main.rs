@@ -1,10 +1,10 @@
 use circom_2_arithc::{
     circom::input::{input_processing::view, Input},
-    program::{build_circuit, ProgramError},
+    circuit::DEFAULT_COMPARISON_BITS,
+    program::{build_circuit, get_prime, ProgramError},
 };
 use dotenv::dotenv;
-use env_logger::init_from_env;
-use serde_json::to_string;
+use env_logger::{init_from_env, Env};
 use std::{
     fs::{self, File},
     io::Write,
@@ -13,7 +13,7 @@ use std::{
 
 fn main() -> Result<(), ProgramError> {
     dotenv().expect("Failed to initialize environment");
-    init_from_env("LOG_LEVEL=info");
+    init_from_env(Env::new().filter_or("LOG_LEVEL", "info"));
 
     let output_path = PathBuf::from(view().value_of("output").unwrap());
 
@@ -26,9 +26,16 @@ fn main() -> Result<(), ProgramError> {
         .ok_or(ProgramError::OutputDirectoryCreationError)?
         .to_path_buf();
 
-    let circuit_json = to_string(&build_circuit(&input)?)?;
+    let mut circuit = build_circuit(&input)?;
+    let circuit_json = circuit.to_json().to_string();
     let output_file_path = Input::build_output(&output_dir, &input.out_wasm_name, "json");
     File::create(output_file_path)?.write_all(circuit_json.as_bytes())?;
 
+    if input.r1cs_flag {
+        circuit.lower_comparisons(DEFAULT_COMPARISON_BITS)?;
+        let r1cs = circuit.to_r1cs(&get_prime(&input))?;
+        r1cs.write(&mut File::create(&input.out_r1cs)?)?;
+    }
+
     Ok(())
 }
@@ -2,47 +2,73 @@
 //!
 //! Handles execution of statements and expressions for arithmetic circuit generation within a `Runtime` environment.
 
-use crate::circuit::{AGateType, ArithmeticCircuit};
-use crate::program::ProgramError;
+use crate::cache::SubCircuit;
+use crate::circuit::{AGateType, ArithmeticCircuit, GateMeta};
+use crate::program::{BuildConfig, ProgramError, SourceLocation};
 use crate::runtime::{
-    generate_u32, increment_indices, u32_to_access, Context, DataAccess, DataType, Runtime, Signal,
-    SubAccess, RETURN_VAR,
+    generate_u32, increment_indices, signal_not_found, u32_to_access, Context, DataAccess,
+    DataType, FunctionReturn, NestedValue, Runtime, Signal, SubAccess, RETURN_VAR,
 };
-use circom_circom_algebra::num_traits::ToPrimitive;
+use circom_circom_algebra::num_traits::{One, ToPrimitive, Zero};
 use circom_program_structure::ast::{
-    Access, AssignOp, Expression, ExpressionInfixOpcode, Statement,
+    Access, AssignOp, Expression, ExpressionInfixOpcode, ExpressionPrefixOpcode, LogArgument, Meta,
+    Statement, VariableType,
 };
 use circom_program_structure::program_archive::ProgramArchive;
+use log::debug;
+use num_bigint_dig::BigInt;
 use std::collections::HashMap;
+use std::io::Write;
 
 /// Processes a sequence of statements.
 pub fn process_statements(
     ac: &mut ArithmeticCircuit,
     runtime: &mut Runtime,
     program_archive: &ProgramArchive,
+    config: &BuildConfig,
     statements: &[Statement],
 ) -> Result<(), ProgramError> {
     for statement in statements {
-        process_statement(ac, runtime, program_archive, statement)?;
+        process_statement(ac, runtime, program_archive, config, statement)?;
     }
 
     Ok(())
 }
 
-/// Processes a single statement.
+/// Processes a single statement, locating the errors it raises in the source.
 pub fn process_statement(
     ac: &mut ArithmeticCircuit,
     runtime: &mut Runtime,
     program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    statement: &Statement,
+) -> Result<(), ProgramError> {
+    debug!("Processing {} statement", statement_kind(statement));
+    execute_statement(ac, runtime, program_archive, config, statement).map_err(|e| {
+        e.with_location(SourceLocation::from_meta(
+            statement.get_meta(),
+            program_archive,
+        ))
+    })
+}
+
+/// Executes a single statement.
+fn execute_statement(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
     statement: &Statement,
 ) -> Result<(), ProgramError> {
     match statement {
-        Statement::Block { stmts, .. } => process_statements(ac, runtime, program_archive, stmts),
+        Statement::Block { stmts, .. } => {
+            process_statements(ac, runtime, program_archive, config, stmts)
+        }
         Statement::InitializationBlock {
             initializations, ..
         } => {
             for stmt in initializations {
-                process_statement(ac, runtime, program_archive, stmt)?;
+                process_statement(ac, runtime, program_archive, config, stmt)?;
             }
 
             Ok(())
@@ -56,39 +82,41 @@ pub fn process_statement(
             let data_type = DataType::try_from(xtype)?;
             let dim_access: Vec<DataAccess> = dimensions
                 .iter()
-                .map(|expression| process_expression(ac, runtime, program_archive, expression))
+                .map(|expression| {
+                    process_expression(ac, runtime, program_archive, config, expression)
+                })
                 .collect::<Result<Vec<DataAccess>, ProgramError>>()?;
 
             let ctx = runtime.current_context()?;
             let dimensions: Vec<u32> = dim_access
                 .iter()
-                .map(|dim_access| {
-                    ctx.get_variable_value(dim_access)?
-                        .ok_or(ProgramError::EmptyDataItem)
-                })
+                .map(|dim_access| get_u32_value(ctx, dim_access))
                 .collect::<Result<Vec<u32>, ProgramError>>()?;
             ctx.declare_item(data_type.clone(), name, &dimensions)?;
 
-            // If the declared item is a signal we should add it to the arithmetic circuit
+            // If the declared item is a signal we should add it to the arithmetic circuit, along with its name
             if data_type == DataType::Signal {
-                let mut signal_access = DataAccess::new(name, Vec::new());
+                add_signals(
+                    ac,
+                    ctx,
+                    &DataAccess::new(name, Vec::new()),
+                    &dimensions,
+                    true,
+                )?;
+            }
 
-                if dimensions.is_empty() {
-                    let signal_id = ctx.get_signal_id(&signal_access)?;
-                    ac.add_signal(signal_id)?;
-                } else {
-                    let mut indices: Vec<u32> = vec![0; dimensions.len()];
+            // Buses get their field signals from an instance of the bus definition
+            if let VariableType::Bus(bus_name, ..) = xtype {
+                let mut indices: Vec<u32> = vec![0; dimensions.len()];
 
-                    loop {
-                        // Set access and get signal id for the current indices
-                        signal_access.set_access(u32_to_access(&indices));
-                        let signal_id = ctx.get_signal_id(&signal_access)?;
-                        ac.add_signal(signal_id)?;
+                loop {
+                    let fields = instantiate_bus(ac, runtime, program_archive, config, bus_name)?;
+                    runtime
+                        .current_context()?
+                        .set_component(&DataAccess::new(name, u32_to_access(&indices)), fields)?;
 
-                        // Increment indices
-                        if !increment_indices(&mut indices, &dimensions)? {
-                            break;
-                        }
+                    if !increment_indices(&mut indices, &dimensions)? {
+                        break;
                     }
                 }
             }
@@ -96,20 +124,32 @@ pub fn process_statement(
             Ok(())
         }
         Statement::While { cond, stmt, .. } => {
-            runtime.push_context(true)?;
+            runtime.push_loop_context()?;
+            let mut iterations: u64 = 0;
             loop {
-                let access = process_expression(ac, runtime, program_archive, cond)?;
+                let access = process_expression(ac, runtime, program_archive, config, cond)?;
                 let result = runtime
                     .current_context()?
                     .get_variable_value(&access)?
-                    .ok_or(ProgramError::EmptyDataItem)?;
+                    .ok_or(ProgramError::EmptyDataItem { location: None })?;
 
-                if result == 0 {
+                if result.is_zero() {
                     break;
                 }
 
+                iterations += 1;
+                if iterations > config.loop_limit {
+                    return Err(ProgramError::OperationError {
+                        message: format!(
+                            "loop limit exceeded after {} iterations",
+                            config.loop_limit
+                        ),
+                        location: None,
+                    });
+                }
+
                 runtime.push_context(true)?;
-                process_statement(ac, runtime, program_archive, stmt)?;
+                process_statement(ac, runtime, program_archive, config, stmt)?;
                 runtime.pop_context(true)?;
             }
             runtime.pop_context(true)?;
@@ -122,16 +162,34 @@ pub fn process_statement(
             else_case,
             ..
         } => {
-            let access = process_expression(ac, runtime, program_archive, cond)?;
+            let access = process_expression(ac, runtime, program_archive, config, cond)?;
+
+            // A signal condition is only known at proving time, so both branches are multiplexed
+            if runtime
+                .current_context()?
+                .get_item_data_type(&access.get_name())?
+                != DataType::Variable
+            {
+                return handle_signal_if_then_else(
+                    ac,
+                    runtime,
+                    program_archive,
+                    config,
+                    &access,
+                    if_case,
+                    else_case.as_deref(),
+                );
+            }
+
             let result = runtime
                 .current_context()?
                 .get_variable_value(&access)?
-                .ok_or(ProgramError::EmptyDataItem)?;
+                .ok_or(ProgramError::EmptyDataItem { location: None })?;
 
-            if result == 0 {
+            if result.is_zero() {
                 if let Some(else_statement) = else_case {
                     runtime.push_context(true)?;
-                    process_statement(ac, runtime, program_archive, else_statement)?;
+                    process_statement(ac, runtime, program_archive, config, else_statement)?;
                     runtime.pop_context(true)?;
                     Ok(())
                 } else {
@@ -139,7 +197,7 @@ pub fn process_statement(
                 }
             } else {
                 runtime.push_context(true)?;
-                process_statement(ac, runtime, program_archive, if_case)?;
+                process_statement(ac, runtime, program_archive, config, if_case)?;
                 runtime.pop_context(true)?;
                 Ok(())
             }
@@ -151,330 +209,1500 @@ pub fn process_statement(
             op,
             ..
         } => {
-            let lh_access = build_access(ac, runtime, program_archive, var, access)?;
-            let rh_access = process_expression(ac, runtime, program_archive, rhe)?;
+            let lh_access = build_access(ac, runtime, program_archive, config, var, access)?;
+
+            // The gates computing a witness assigned with `<--` don't constrain it
+            let is_hint = matches!(op, AssignOp::AssignSignal);
+            let first_rhe_gate = ac.gate_count();
+            if is_hint {
+                ac.set_hint_mode(true);
+            }
+            let rh_access = process_expression(ac, runtime, program_archive, config, rhe);
+            if is_hint {
+                ac.set_hint_mode(false);
+            }
+
+            handle_substitution(ac, runtime, &lh_access, op, &rh_access?, first_rhe_gate)
+        }
+        Statement::Return { value, .. } => {
+            let return_access = process_expression(ac, runtime, program_archive, config, value)?;
 
+            // Arrays are returned whole, by copying their elements into a return variable of the same dimensions
             let ctx = runtime.current_context()?;
-            match ctx.get_item_data_type(var)? {
-                DataType::Signal => {
-                    // Connect the generated gate output to the given signal
-                    let given_output_id = ctx.get_signal_id(&lh_access)?;
-                    let gate_output_id = get_signal_for_access(ac, ctx, &rh_access)?;
+            let (dimensions, values) = get_variable_values(ctx, &return_access)?;
+            if dimensions.is_empty() && values[0].is_none() {
+                return Err(ProgramError::EmptyDataItem { location: None });
+            }
 
-                    ac.add_connection(gate_output_id, given_output_id)?;
+            ctx.declare_item(DataType::Variable, RETURN_VAR, &dimensions)?;
+            set_variable_values(
+                ctx,
+                &DataAccess::new(RETURN_VAR, vec![]),
+                &dimensions,
+                values,
+            )
+        }
+        Statement::MultSubstitution { lhe, op, rhe, .. } => {
+            let targets = match lhe {
+                Expression::Tuple { values, .. } => values,
+                _ => {
+                    return Err(ProgramError::NotImplemented(
+                        "MultSubstitution into a non tuple expression".to_string(),
+                    ))
                 }
-                DataType::Variable => {
-                    // Assign the evaluated right-hand side to the left-hand side
-                    let value = ctx.get_variable_value(&rh_access)?;
-                    ctx.set_variable(&lh_access, value)?;
+            };
+
+            // Get an access for each value of the right-hand side
+            let rh_accesses = match rhe {
+                Expression::Tuple { values, .. } => values
+                    .iter()
+                    .map(|value| process_expression(ac, runtime, program_archive, config, value))
+                    .collect::<Result<Vec<DataAccess>, ProgramError>>()?,
+                _ => {
+                    let component_access =
+                        process_expression(ac, runtime, program_archive, config, rhe)?;
+                    let signal_map = runtime
+                        .current_context()?
+                        .get_component_map(&component_access)?;
+
+                    // Template outputs are taken in declaration order, tuple elements by position
+                    let component_name = match rhe {
+                        Expression::Call { id, .. } | Expression::AnonymousComp { id, .. } => {
+                            id.clone()
+                        }
+                        _ => component_access.get_name(),
+                    };
+                    let outputs = match rhe {
+                        Expression::Call { .. } | Expression::AnonymousComp { .. } => {
+                            get_template_outputs(program_archive, rhe)?
+                        }
+                        _ => (0..signal_map.len())
+                            .map(|index| index.to_string())
+                            .collect(),
+                    };
+
+                    outputs
+                        .iter()
+                        .map(|output| {
+                            if !signal_map.contains_key(output) {
+                                return Err(
+                                    signal_not_found(output, &component_name, &signal_map).into()
+                                );
+                            }
+
+                            Ok(append_field(&component_access, output))
+                        })
+                        .collect::<Result<Vec<DataAccess>, ProgramError>>()?
                 }
-                DataType::Component => match op {
-                    AssignOp::AssignVar => {
-                        // Component assignment
-                        let signal_map = ctx.get_component_map(&rh_access)?;
-                        ctx.set_component(&lh_access, signal_map)?;
-                    }
-                    AssignOp::AssignConstraintSignal => {
-                        // Add connection
-                        let component_signal = ctx.get_component_signal_id(&lh_access)?;
-                        let assigned_signal = get_signal_for_access(ac, ctx, &rh_access)?;
+            };
+
+            if targets.len() != rh_accesses.len() {
+                return Err(ProgramError::OperationError {
+                    message: format!(
+                        "Multiple substitution of {} values into {} targets",
+                        rh_accesses.len(),
+                        targets.len()
+                    ),
+                    location: None,
+                });
+            }
 
-                        ac.add_connection(assigned_signal, component_signal)?;
+            // Assign each value to its target
+            for (target, rh_access) in targets.iter().zip(rh_accesses.iter()) {
+                match target {
+                    // Discarded values
+                    Expression::Variable { name, .. } if name == "_" => {}
+                    Expression::Variable { name, access, .. } => {
+                        let lh_access =
+                            build_access(ac, runtime, program_archive, config, name, access)?;
+                        let first_rhe_gate = ac.gate_count();
+                        handle_substitution(
+                            ac,
+                            runtime,
+                            &lh_access,
+                            op,
+                            rh_access,
+                            first_rhe_gate,
+                        )?;
                     }
-                    _ => return Err(ProgramError::OperationNotSupported),
-                },
+                    _ => {
+                        return Err(ProgramError::NotImplemented(
+                            "MultSubstitution into a tuple element that isn't a variable"
+                                .to_string(),
+                        ))
+                    }
+                }
             }
 
             Ok(())
         }
-        Statement::Return { value, .. } => {
-            let return_access = process_expression(ac, runtime, program_archive, value)?;
+        Statement::UnderscoreSubstitution { rhe, .. } => {
+            // The expression is still processed for its side effects (e.g. component instantiation),
+            // but its output is left unconnected
+            process_expression(ac, runtime, program_archive, config, rhe)?;
+            Ok(())
+        }
+        Statement::ConstraintEquality { lhe, rhe, .. } => {
+            let lh_access = process_expression(ac, runtime, program_archive, config, lhe)?;
+            let rh_access = process_expression(ac, runtime, program_archive, config, rhe)?;
 
             let ctx = runtime.current_context()?;
-            let return_value = ctx
-                .get_variable_value(&return_access)?
-                .ok_or(ProgramError::EmptyDataItem)?;
+            handle_constraint_equality(ac, ctx, &lh_access, &rh_access)
+        }
+        Statement::LogCall { args, .. } => {
+            let mut messages = Vec::new();
 
-            ctx.declare_item(DataType::Variable, RETURN_VAR, &[])?;
-            ctx.set_variable(&DataAccess::new(RETURN_VAR, vec![]), Some(return_value))?;
+            for arg in args {
+                match arg {
+                    LogArgument::LogStr(message) => messages.push(message.clone()),
+                    LogArgument::LogExp(expression) => {
+                        let access =
+                            process_expression(ac, runtime, program_archive, config, expression)?;
+
+                        // Signal values are only known at witness generation, so we log their id
+                        let ctx = runtime.current_context()?;
+                        let message = match ctx.get_item_data_type(&access.get_name())? {
+                            DataType::Variable => match ctx.get_variable_value(&access)? {
+                                Some(value) => value.to_string(),
+                                None => "undefined".to_string(),
+                            },
+                            DataType::Signal => format_log_signal(ctx.get_signal_id(&access)?),
+                            DataType::Component | DataType::Bus => {
+                                format_log_signal(ctx.get_component_signal_id(&access)?)
+                            }
+                        };
+                        messages.push(message);
+                    }
+                }
+            }
+
+            writeln!(runtime.log_writer(), "{}", messages.join(" "))?;
 
-            Ok(())
-        }
-        Statement::MultSubstitution { meta, lhe, op, rhe } => {
-            println!("Statement not implemented: MultSubstitution");
-            Ok(())
-        }
-        Statement::UnderscoreSubstitution { meta, op, rhe } => {
-            println!("Statement not implemented: UnderscoreSubstitution");
-            Ok(())
-        }
-        Statement::ConstraintEquality { meta, lhe, rhe } => {
-            println!("Statement not implemented: ConstraintEquality");
-            Ok(())
-        }
-        Statement::LogCall { meta, args } => {
-            println!("Statement not implemented: LogCall");
             Ok(())
         }
         Statement::Assert { meta, arg } => {
-            println!("Statement not implemented: Assert");
+            let access = process_expression(ac, runtime, program_archive, config, arg)?;
+
+            let ctx = runtime.current_context()?;
+            if ctx.get_item_data_type(&access.get_name())? == DataType::Variable {
+                // Evaluate the assertion at compile time
+                let value = ctx
+                    .get_variable_value(&access)?
+                    .ok_or(ProgramError::EmptyDataItem { location: None })?;
+
+                if value.is_zero() {
+                    return Err(ProgramError::AssertionFailed {
+                        location: SourceLocation::from_meta(meta, program_archive),
+                    });
+                }
+            } else {
+                // An assertion over a signal is constrained to hold in the circuit
+                let signal_id = get_signal_for_access(ac, ctx, &access)?;
+                let one_id = ac.add_const(BigInt::one())?;
+                ac.add_constraint(signal_id, one_id)?;
+            }
+
             Ok(())
         }
     }
 }
 
-/// Processes an expression and returns an access to the result.
-pub fn process_expression(
+/// Handles an if-then-else statement whose condition is a signal.
+/// Both branches must assign the same signals, each of them being connected to a multiplexer of the values
+/// assigned by the branches, selected by the condition.
+fn handle_signal_if_then_else(
     ac: &mut ArithmeticCircuit,
     runtime: &mut Runtime,
     program_archive: &ProgramArchive,
-    expression: &Expression,
-) -> Result<DataAccess, ProgramError> {
-    match expression {
-        Expression::Call { id, args, .. } => handle_call(ac, runtime, program_archive, id, args),
-        Expression::InfixOp {
-            lhe, infix_op, rhe, ..
-        } => handle_infix_op(ac, runtime, program_archive, infix_op, lhe, rhe),
-        Expression::Number(_, value) => {
-            let access = runtime
-                .current_context()?
-                .declare_random_item(DataType::Variable)?;
+    config: &BuildConfig,
+    cond_access: &DataAccess,
+    if_case: &Statement,
+    else_case: Option<&Statement>,
+) -> Result<(), ProgramError> {
+    let else_case = else_case.ok_or_else(|| ProgramError::OperationError {
+        message: "If statement with a signal condition must have an else branch".to_string(),
+        location: None,
+    })?;
 
-            runtime.current_context()?.set_variable(
-                &access,
-                Some(value.to_u32().ok_or(ProgramError::ParsingError)?),
-            )?;
+    runtime.push_context(true)?;
+    let true_assignments = get_branch_assignments(ac, runtime, program_archive, config, if_case)?;
+    runtime.pop_context(true)?;
+    runtime.push_context(true)?;
+    let false_assignments =
+        get_branch_assignments(ac, runtime, program_archive, config, else_case)?;
+    runtime.pop_context(true)?;
 
-            Ok(access)
-        }
-        Expression::Variable { name, access, .. } => {
-            build_access(ac, runtime, program_archive, name, access)
-        }
-        Expression::PrefixOp {
-            meta,
-            prefix_op,
-            rhe,
-        } => {
-            println!("Expression not implemented:PrefixOp");
-            Ok(DataAccess::new("", vec![]))
-        }
-        Expression::InlineSwitchOp {
-            meta,
-            cond,
-            if_true,
-            if_false,
-        } => {
-            println!("Expression not implemented:InlineSwitchOp");
-            Ok(DataAccess::new("", vec![]))
-        }
-        Expression::ParallelOp { meta, rhe } => {
-            println!("Expression not implemented:ParallelOp");
-            Ok(DataAccess::new("", vec![]))
-        }
-        Expression::AnonymousComp {
-            meta,
-            id,
-            is_parallel,
-            params,
-            signals,
-            names,
-        } => {
-            println!("Expression not implemented:AnonymousComp");
-            Ok(DataAccess::new("", vec![]))
-        }
-        Expression::ArrayInLine { meta, values } => {
-            println!("Expression not implemented:ArrayInLine");
-            Ok(DataAccess::new("", vec![]))
-        }
-        Expression::Tuple { meta, values } => {
-            println!("Expression not implemented:Tuple");
-            Ok(DataAccess::new("", vec![]))
-        }
-        Expression::UniformArray {
-            meta,
-            value,
-            dimension,
-        } => {
-            println!("Expression not implemented: UniformArray");
-            Ok(DataAccess::new("", vec![]))
-        }
+    if true_assignments.len() != false_assignments.len() {
+        return Err(ProgramError::OperationError {
+            message:
+                "Branches of an if statement with a signal condition must assign the same signals"
+                    .to_string(),
+            location: None,
+        });
+    }
+
+    for (lh_access, op, true_id) in &true_assignments {
+        let false_id = false_assignments
+            .iter()
+            .find(|(access, false_op, _)| access == lh_access && false_op == op)
+            .map(|(_, _, id)| *id)
+            .ok_or_else(|| {
+                ProgramError::OperationError { message: format!(
+                    "Signal {} is only assigned in one branch of an if statement with a signal condition",
+                    lh_access.get_name()
+                ), location: None }
+            })?;
+
+        let ctx = runtime.current_context()?;
+        let cond_id = get_signal_for_access(ac, ctx, cond_access)?;
+        let mux_access = add_mux(ac, ctx, cond_id, *true_id, false_id)?;
+
+        let first_rhe_gate = ac.gate_count();
+        handle_substitution(ac, runtime, lh_access, op, &mux_access, first_rhe_gate)?;
     }
+
+    Ok(())
 }
 
-/// Handles function and template calls.
-fn handle_call(
+/// Evaluates the signal assignments of a branch without applying them, returning their target, operator and the id
+/// of the signal holding their value. Values are resolved in the context of the branch, as the signals it declares
+/// for intermediate results are dropped with it. Branches can only contain signal assignments, possibly grouped in
+/// blocks.
+fn get_branch_assignments(
     ac: &mut ArithmeticCircuit,
     runtime: &mut Runtime,
     program_archive: &ProgramArchive,
-    id: &str,
-    args: &[Expression],
-) -> Result<DataAccess, ProgramError> {
-    // Determine if the call is to a function or a template and get argument names and body
-    let is_function = program_archive.contains_function(id);
-    let (arg_names, body) = if is_function {
-        let function_data = program_archive.get_function_data(id);
-        (
-            function_data.get_name_of_params().clone(),
-            function_data.get_body_as_vec().to_vec(),
-        )
-    } else if program_archive.contains_template(id) {
-        let template_data = program_archive.get_template_data(id);
-        (
-            template_data.get_name_of_params().clone(),
-            template_data.get_body_as_vec().to_vec(),
-        )
-    } else {
-        return Err(ProgramError::UndefinedFunctionOrTemplate);
-    };
-
-    let arg_values = args
-        .iter()
-        .map(|arg_expr| {
-            process_expression(ac, runtime, program_archive, arg_expr).and_then(|value_access| {
-                runtime
-                    .current_context()?
-                    .get_variable_value(&value_access)?
-                    .ok_or(ProgramError::EmptyDataItem)
-            })
-        })
-        .collect::<Result<Vec<u32>, ProgramError>>()?;
+    config: &BuildConfig,
+    statement: &Statement,
+) -> Result<Vec<(DataAccess, AssignOp, u32)>, ProgramError> {
+    match statement {
+        Statement::Block { stmts, .. } => {
+            let mut assignments = Vec::new();
+            for stmt in stmts {
+                assignments.extend(get_branch_assignments(
+                    ac,
+                    runtime,
+                    program_archive,
+                    config,
+                    stmt,
+                )?);
+            }
 
-    // Create a new execution context
-    runtime.push_context(false)?;
+            Ok(assignments)
+        }
+        Statement::Substitution {
+            var,
+            access,
+            rhe,
+            op,
+            ..
+        } if *op != AssignOp::AssignVar => {
+            let lh_access = build_access(ac, runtime, program_archive, config, var, access)?;
+            let rh_access = process_expression(ac, runtime, program_archive, config, rhe)?;
+            let rh_id = get_signal_for_access(ac, runtime.current_context()?, &rh_access)?;
 
-    // Set arguments in the new context
-    for (arg_name, &arg_value) in arg_names.iter().zip(&arg_values) {
-        runtime
-            .current_context()?
-            .declare_item(DataType::Variable, arg_name, &[])?;
-        runtime
-            .current_context()?
-            .set_variable(&DataAccess::new(arg_name, vec![]), Some(arg_value))?;
+            Ok(vec![(lh_access, *op, rh_id)])
+        }
+        _ => Err(ProgramError::OperationError {
+            message:
+                "Only signal assignments are supported in if statements with a signal condition"
+                    .to_string(),
+            location: None,
+        }),
     }
+}
 
-    // Process the function/template body
-    process_statements(ac, runtime, program_archive, &body)?;
+/// Records the equality between the items behind both accesses. Arrays are constrained element by element.
+fn handle_constraint_equality(
+    ac: &mut ArithmeticCircuit,
+    ctx: &Context,
+    lh_access: &DataAccess,
+    rh_access: &DataAccess,
+) -> Result<(), ProgramError> {
+    let dimensions = get_matching_dimensions(ctx, lh_access, rh_access)?;
+    if !dimensions.is_empty() {
+        let mut indices: Vec<u32> = vec![0; dimensions.len()];
 
-    // Get return values
-    let mut function_return: Option<u32> = None;
-    let mut component_return: HashMap<String, Signal> = HashMap::new();
+        loop {
+            handle_constraint_equality(
+                ac,
+                ctx,
+                &append_indices(lh_access, &indices),
+                &append_indices(rh_access, &indices),
+            )?;
 
-    if is_function {
-        if let Ok(value) = runtime
-            .current_context()?
-            .get_variable_value(&DataAccess::new(RETURN_VAR, vec![]))
-        {
-            function_return = value;
+            if !increment_indices(&mut indices, &dimensions)? {
+                break;
+            }
         }
-    } else {
-        // Retrieve input and output signals
-        let template_data = program_archive.get_template_data(id);
-        let input_signals = template_data.get_inputs();
-        let output_signals = template_data.get_outputs();
 
-        // Store ids in the component
-        for (signal, _) in input_signals.iter().chain(output_signals.iter()) {
-            let ids = runtime.current_context()?.get_signal(signal)?;
-            component_return.insert(signal.to_string(), ids);
-        }
+        return Ok(());
     }
 
-    // Return to parent context
-    runtime.pop_context(false)?;
-    let ctx = runtime.current_context()?;
-    let return_access =
-        DataAccess::new(&format!("{}_{}_{}", id, RETURN_VAR, generate_u32()), vec![]);
+    // If both sides are known at compile time the constraint can be checked directly
+    if ctx.get_item_data_type(&lh_access.get_name())? == DataType::Variable
+        && ctx.get_item_data_type(&rh_access.get_name())? == DataType::Variable
+    {
+        let lhs_value = ctx
+            .get_variable_value(lh_access)?
+            .ok_or(ProgramError::EmptyDataItem { location: None })?;
+        let rhs_value = ctx
+            .get_variable_value(rh_access)?
+            .ok_or(ProgramError::EmptyDataItem { location: None })?;
 
-    if is_function {
-        ctx.declare_item(DataType::Variable, &return_access.get_name(), &[])?;
-        ctx.set_variable(&return_access, function_return)?;
-    } else {
-        ctx.declare_item(DataType::Component, &return_access.get_name(), &[])?;
-        ctx.set_component(&return_access, component_return)?;
+        if lhs_value != rhs_value {
+            return Err(ProgramError::OperationError {
+                message: format!("Constraint not satisfied: {} === {}", lhs_value, rhs_value),
+                location: None,
+            });
+        }
+
+        return Ok(());
     }
 
-    Ok(return_access)
-}
+    // Record the equality between both sides in the circuit
+    let lhs_id = get_signal_for_access(ac, ctx, lh_access)?;
+    let rhs_id = get_signal_for_access(ac, ctx, rh_access)?;
 
-/// Handles an infix operation.
-/// - If both inputs are variables, it directly computes the operation.
-/// - If one or both inputs are signals, it constructs the corresponding circuit gate.
-/// Returns the access to a variable containing the result of the operation or the signal of the output gate.
-fn handle_infix_op(
-    ac: &mut ArithmeticCircuit,
-    runtime: &mut Runtime,
-    program_archive: &ProgramArchive,
-    op: &ExpressionInfixOpcode,
-    lhe: &Expression,
-    rhe: &Expression,
-) -> Result<DataAccess, ProgramError> {
-    let lhe_access = process_expression(ac, runtime, program_archive, lhe)?;
-    let rhe_access = process_expression(ac, runtime, program_archive, rhe)?;
+    ac.add_constraint(lhs_id, rhs_id)?;
 
-    let ctx = runtime.current_context()?;
+    Ok(())
+}
 
-    // Determine the data types of the left and right operands
-    let lhs_data_type = ctx.get_item_data_type(&lhe_access.get_name())?;
-    let rhs_data_type = ctx.get_item_data_type(&rhe_access.get_name())?;
+/// Returns the dimensions shared by the items behind both accesses.
+fn get_matching_dimensions(
+    ctx: &Context,
+    lh_access: &DataAccess,
+    rh_access: &DataAccess,
+) -> Result<Vec<u32>, ProgramError> {
+    let lh_dimensions = ctx.get_item_dimensions(lh_access)?;
+    let rh_dimensions = ctx.get_item_dimensions(rh_access)?;
 
-    // Handle the case where both inputs are variables
-    if lhs_data_type == DataType::Variable && rhs_data_type == DataType::Variable {
-        let lhs_value = ctx
-            .get_variable_value(&lhe_access)?
-            .ok_or(ProgramError::EmptyDataItem)?;
-        let rhs_value = ctx
-            .get_variable_value(&rhe_access)?
-            .ok_or(ProgramError::EmptyDataItem)?;
+    if lh_dimensions != rh_dimensions {
+        return Err(ProgramError::OperationError {
+            message: format!(
+                "Dimensions mismatch: {:?} and {:?}",
+                lh_dimensions, rh_dimensions
+            ),
+            location: None,
+        });
+    }
 
-        let op_res = execute_op(lhs_value, rhs_value, op)?;
-        let item_access = ctx.declare_random_item(DataType::Variable)?;
-        ctx.set_variable(&item_access, Some(op_res))?;
+    Ok(rh_dimensions)
+}
 
-        return Ok(item_access);
+/// Adds the signals of a declared signal item to the arithmetic circuit.
+/// If `named` is set, the item name is registered as the name of the signals, followed by their indices.
+fn add_signals(
+    ac: &mut ArithmeticCircuit,
+    ctx: &Context,
+    access: &DataAccess,
+    dimensions: &[u32],
+    named: bool,
+) -> Result<(), ProgramError> {
+    if dimensions.is_empty() {
+        let signal_id = ctx.get_signal_id(access)?;
+        ac.add_signal(signal_id)?;
+        if named {
+            ac.register_signal_name(signal_id, &access.get_name())?;
+        }
+        return Ok(());
     }
 
-    // Handle cases where one or both inputs are signals
-    let lhs_id = get_signal_for_access(ac, ctx, &lhe_access)?;
-    let rhs_id = get_signal_for_access(ac, ctx, &rhe_access)?;
+    let mut indices: Vec<u32> = vec![0; dimensions.len()];
 
-    // Construct the corresponding circuit gate
-    let gate_type = AGateType::from(op);
-    let output_signal = ctx.declare_random_item(DataType::Signal)?;
-    let output_id = ctx.get_signal_id(&output_signal)?;
+    loop {
+        // Get the signal id for the current indices
+        let signal_id = ctx.get_signal_id(&append_indices(access, &indices))?;
+        ac.add_signal(signal_id)?;
+        if named {
+            let index_names: String = indices.iter().map(|index| format!("[{}]", index)).collect();
+            ac.register_signal_name(signal_id, &format!("{}{}", access.get_name(), index_names))?;
+        }
 
-    // Add output signal and gate to the circuit
-    ac.add_signal(output_id)?;
-    ac.add_gate(gate_type, lhs_id, rhs_id, output_id)?;
+        // Increment indices
+        if !increment_indices(&mut indices, dimensions)? {
+            break;
+        }
+    }
 
-    Ok(output_signal)
+    Ok(())
 }
 
-/// Returns a signal id for a given access
-/// - If the access is a signal or a component, it returns the corresponding signal id.
-/// - If the access is a variable, it adds a constant variable to the circuit and returns the corresponding signal id.
-fn get_signal_for_access(
-    ac: &mut ArithmeticCircuit,
+/// Returns the dimensions of the variable item behind an access, with the values of its elements in row-major order.
+/// A single value has no dimensions.
+fn get_variable_values(
     ctx: &Context,
     access: &DataAccess,
-) -> Result<u32, ProgramError> {
-    match ctx.get_item_data_type(&access.get_name())? {
-        DataType::Signal => Ok(ctx.get_signal_id(access)?),
-        DataType::Variable => {
-            let value = ctx
-                .get_variable_value(access)?
-                .ok_or(ProgramError::EmptyDataItem)?;
-            ac.add_const(value)?;
-            Ok(value)
+) -> Result<(Vec<u32>, Vec<Option<BigInt>>), ProgramError> {
+    let dimensions = ctx.get_item_dimensions(access)?;
+    let mut values = Vec::new();
+    let mut indices: Vec<u32> = vec![0; dimensions.len()];
+
+    loop {
+        values.push(ctx.get_variable_value(&append_indices(access, &indices))?);
+
+        if !increment_indices(&mut indices, &dimensions)? {
+            break;
         }
-        DataType::Component => Ok(ctx.get_component_signal_id(access)?),
     }
+
+    Ok((dimensions, values))
 }
 
-/// Builds a DataAccess from an Access array
-pub fn build_access(
-    ac: &mut ArithmeticCircuit,
+/// Sets the elements of the variable item behind an access from values in row-major order.
+fn set_variable_values(
+    ctx: &mut Context,
+    access: &DataAccess,
+    dimensions: &[u32],
+    values: Vec<Option<BigInt>>,
+) -> Result<(), ProgramError> {
+    let mut indices: Vec<u32> = vec![0; dimensions.len()];
+
+    for value in values {
+        ctx.set_variable(&append_indices(access, &indices), value)?;
+        increment_indices(&mut indices, dimensions)?;
+    }
+
+    Ok(())
+}
+
+/// Returns a copy of an access with the given array indices appended.
+fn append_indices(access: &DataAccess, indices: &[u32]) -> DataAccess {
+    let mut sub_access = access.get_access().clone();
+    sub_access.extend(u32_to_access(indices));
+    DataAccess::new(&access.get_name(), sub_access)
+}
+
+/// Assigns the item behind the right-hand side access to the data item of the left-hand side access.
+/// Arrays are assigned element by element. `first_rhe_gate` is the number of gates before the right-hand side was
+/// evaluated, so that the witness hints it added can be told apart.
+fn handle_substitution(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    lh_access: &DataAccess,
+    op: &AssignOp,
+    rh_access: &DataAccess,
+    first_rhe_gate: u32,
+) -> Result<(), ProgramError> {
+    let ctx = runtime.current_context()?;
+
+    let dimensions = get_matching_dimensions(ctx, lh_access, rh_access)?;
+    if !dimensions.is_empty() {
+        let mut indices: Vec<u32> = vec![0; dimensions.len()];
+
+        loop {
+            handle_substitution(
+                ac,
+                runtime,
+                &append_indices(lh_access, &indices),
+                op,
+                &append_indices(rh_access, &indices),
+                first_rhe_gate,
+            )?;
+
+            if !increment_indices(&mut indices, &dimensions)? {
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    match ctx.get_item_data_type(&lh_access.get_name())? {
+        DataType::Signal => {
+            // Connect the generated gate output to the given signal
+            let given_output_id = ctx.get_signal_id(lh_access)?;
+            let gate_output_id = get_assigned_signal(ac, ctx, op, rh_access, first_rhe_gate)?;
+
+            assign_signal(ac, given_output_id)?;
+            ac.add_connection(gate_output_id, given_output_id)?;
+        }
+        DataType::Variable => {
+            // Assign the evaluated right-hand side to the left-hand side
+            let value = ctx.get_variable_value(rh_access)?;
+            ctx.set_variable(lh_access, value)?;
+        }
+        DataType::Component => match op {
+            AssignOp::AssignVar => {
+                // Component assignment
+                let signal_map = ctx.get_component_map(rh_access)?;
+                ctx.set_component(lh_access, signal_map)?;
+                if ctx.is_parallel(rh_access)? {
+                    ctx.mark_parallel(lh_access)?;
+                }
+            }
+            AssignOp::AssignConstraintSignal | AssignOp::AssignSignal => {
+                // Add connection
+                let component_signal = ctx.get_component_signal_id(lh_access)?;
+                let assigned_signal = get_assigned_signal(ac, ctx, op, rh_access, first_rhe_gate)?;
+
+                assign_signal(ac, component_signal)?;
+                ac.add_connection(assigned_signal, component_signal)?;
+            }
+        },
+        DataType::Bus => {
+            if has_field_access(lh_access) {
+                let field_signal = ctx.get_component_signal_id(lh_access)?;
+                let assigned_signal = get_assigned_signal(ac, ctx, op, rh_access, first_rhe_gate)?;
+
+                assign_signal(ac, field_signal)?;
+                ac.add_connection(assigned_signal, field_signal)?;
+            } else {
+                // Whole bus assignment, connected field by field
+                let fields: Vec<String> = ctx.get_component_map(lh_access)?.into_keys().collect();
+                for field in fields {
+                    handle_substitution(
+                        ac,
+                        runtime,
+                        &append_field(lh_access, &field),
+                        op,
+                        &append_field(rh_access, &field),
+                        first_rhe_gate,
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the signal holding the value assigned by a substitution to a signal.
+/// A value assigned with `<--` that isn't computed by a witness hint of its right-hand side, like a signal or a
+/// constant, is copied by a hint gate, so that the assigned signal is left unconstrained rather than connected to it.
+fn get_assigned_signal(
+    ac: &mut ArithmeticCircuit,
+    ctx: &mut Context,
+    op: &AssignOp,
+    rh_access: &DataAccess,
+    first_rhe_gate: u32,
+) -> Result<u32, ProgramError> {
+    let signal_id = get_signal_for_access(ac, ctx, rh_access)?;
+    if *op != AssignOp::AssignSignal || is_new_hint_output(ac, signal_id, first_rhe_gate)? {
+        return Ok(signal_id);
+    }
+
+    let zero_id = ac.add_const(BigInt::zero())?;
+    ac.set_hint_mode(true);
+    let copy_access = add_gate_with_output(ac, ctx, AGateType::AAdd, signal_id, Some(zero_id));
+    ac.set_hint_mode(false);
+
+    Ok(ctx.get_signal_id(&copy_access?)?)
+}
+
+/// Checks if a signal is the output of a witness hint gate added from the given gate on.
+/// This is the case of the values of `<--` assignments computed by an operation of their right-hand side.
+fn is_new_hint_output(
+    ac: &ArithmeticCircuit,
+    signal_id: u32,
+    first_gate: u32,
+) -> Result<bool, ProgramError> {
+    let node_id = ac.get_signal_node(signal_id)?.get_id();
+
+    Ok(ac
+        .get_gates()
+        .iter()
+        .skip(first_gate as usize)
+        .any(|gate| ac.is_hint_gate(gate.get_id()) && gate.get_output() == node_id))
+}
+
+/// Records the assignment of a signal, which circom only allows once.
+fn assign_signal(ac: &mut ArithmeticCircuit, signal_id: u32) -> Result<(), ProgramError> {
+    if !ac.assign_signal(signal_id) {
+        return Err(ProgramError::SignalAlreadyAssigned {
+            name: ac.signal_label(signal_id),
+            location: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// Instantiates a bus definition, returning the signals of its fields.
+fn instantiate_bus(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    bus_name: &str,
+) -> Result<HashMap<String, Signal>, ProgramError> {
+    if !program_archive.contains_bus(bus_name) {
+        return Err(ProgramError::UndefinedFunctionOrTemplate { location: None });
+    }
+    let body = program_archive.get_bus_data(bus_name).get_body_as_vec();
+
+    // The fields are the signals declared in a fresh context
+    runtime.push_context(false)?;
+    process_statements(ac, runtime, program_archive, config, body)?;
+    let fields = runtime.current_context()?.get_signals();
+    runtime.pop_context(false)?;
+
+    Ok(fields)
+}
+
+/// Checks if an access refers to a field of a component or bus.
+fn has_field_access(access: &DataAccess) -> bool {
+    access
+        .get_access()
+        .iter()
+        .any(|sub_access| matches!(sub_access, SubAccess::Component(_)))
+}
+
+/// Returns a copy of an access with the given field access appended.
+fn append_field(access: &DataAccess, field: &str) -> DataAccess {
+    let mut sub_access = access.get_access().clone();
+    sub_access.push(SubAccess::Component(field.to_string()));
+    DataAccess::new(&access.get_name(), sub_access)
+}
+
+/// Returns the output signal names, in declaration order, of the template instantiated by an expression.
+fn get_template_outputs(
+    program_archive: &ProgramArchive,
+    expression: &Expression,
+) -> Result<Vec<String>, ProgramError> {
+    let id = match expression {
+        Expression::Call { id, .. } | Expression::AnonymousComp { id, .. } => id,
+        _ => return Err(ProgramError::OperationNotSupported { location: None }),
+    };
+
+    if !program_archive.contains_template(id) {
+        return Err(ProgramError::UndefinedFunctionOrTemplate { location: None });
+    }
+
+    Ok(program_archive
+        .get_template_data(id)
+        .get_declaration_outputs()
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+/// Formats a signal argument of a `log` call.
+fn format_log_signal(signal_id: u32) -> String {
+    format!(
+        "<signal {}: value unknown until witness generation>",
+        signal_id
+    )
+}
+
+/// Processes an expression and returns an access to the result, locating the errors it raises in the source.
+pub fn process_expression(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    expression: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    debug!("Processing {} expression", expression_kind(expression));
+    evaluate_expression(ac, runtime, program_archive, config, expression).map_err(|e| {
+        e.with_location(SourceLocation::from_meta(
+            expression.get_meta(),
+            program_archive,
+        ))
+    })
+}
+
+/// Evaluates an expression and returns an access to the result.
+fn evaluate_expression(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    expression: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    match expression {
+        Expression::Call { id, args, .. } => {
+            handle_call(ac, runtime, program_archive, config, id, args)
+        }
+        Expression::InfixOp {
+            meta,
+            lhe,
+            infix_op,
+            rhe,
+        } => handle_infix_op(
+            ac,
+            runtime,
+            program_archive,
+            config,
+            meta,
+            infix_op,
+            lhe,
+            rhe,
+        ),
+        Expression::Number(_, value) => {
+            let value = modulo(value, runtime.get_prime());
+            let access = runtime
+                .current_context()?
+                .declare_random_item(DataType::Variable)?;
+
+            runtime
+                .current_context()?
+                .set_variable(&access, Some(value))?;
+
+            Ok(access)
+        }
+        Expression::Variable { name, access, .. } => {
+            build_access(ac, runtime, program_archive, config, name, access)
+        }
+        Expression::PrefixOp { prefix_op, rhe, .. } => {
+            handle_prefix_op(ac, runtime, program_archive, config, prefix_op, rhe)
+        }
+        Expression::InlineSwitchOp {
+            cond,
+            if_true,
+            if_false,
+            ..
+        } => handle_inline_switch_op(
+            ac,
+            runtime,
+            program_archive,
+            config,
+            cond,
+            if_true,
+            if_false,
+        ),
+        Expression::ParallelOp { rhe, .. } => {
+            // The parallel annotation doesn't change the circuit, it's only recorded on the component
+            let access = process_expression(ac, runtime, program_archive, config, rhe)?;
+            let ctx = runtime.current_context()?;
+            if ctx.get_item_data_type(&access.get_name())? == DataType::Component {
+                ctx.mark_parallel(&access)?;
+            }
+
+            Ok(access)
+        }
+        Expression::AnonymousComp {
+            id,
+            is_parallel,
+            params,
+            signals,
+            names,
+            ..
+        } => handle_anonymous_component(
+            ac,
+            runtime,
+            program_archive,
+            config,
+            id,
+            *is_parallel,
+            params,
+            signals,
+            names,
+        ),
+        Expression::ArrayInLine { values, .. } => {
+            handle_array_in_line(ac, runtime, program_archive, config, values)
+        }
+        Expression::Tuple { values, .. } => {
+            handle_tuple(ac, runtime, program_archive, config, values)
+        }
+        Expression::UniformArray {
+            value, dimension, ..
+        } => handle_uniform_array(ac, runtime, program_archive, config, value, dimension),
+    }
+}
+
+/// Handles function and template calls.
+fn handle_call(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    id: &str,
+    args: &[Expression],
+) -> Result<DataAccess, ProgramError> {
+    // Determine if the call is to a function or a template and get argument names and body
+    // They're borrowed from the program archive, which outlives the call, so they aren't copied on every call
+    let is_function = program_archive.contains_function(id);
+    let (arg_names, body): (&[String], &[Statement]) = if is_function {
+        let function_data = program_archive.get_function_data(id);
+        (
+            function_data.get_name_of_params(),
+            function_data.get_body_as_vec(),
+        )
+    } else if program_archive.contains_template(id) {
+        let template_data = program_archive.get_template_data(id);
+        (
+            template_data.get_name_of_params(),
+            template_data.get_body_as_vec(),
+        )
+    } else {
+        return Err(ProgramError::UndefinedFunctionOrTemplate { location: None });
+    };
+
+    let arg_values = args
+        .iter()
+        .map(|arg_expr| {
+            process_expression(ac, runtime, program_archive, config, arg_expr).and_then(
+                |value_access| {
+                    runtime
+                        .current_context()?
+                        .get_variable_value(&value_access)?
+                        .ok_or(ProgramError::EmptyDataItem { location: None })
+                },
+            )
+        })
+        .collect::<Result<Vec<BigInt>, ProgramError>>()?;
+
+    // Templates without inputs only depend on their parameters, so their instances can be shared when memoizing
+    let is_cacheable = config.enable_template_memoization
+        && !is_function
+        && program_archive
+            .get_template_data(id)
+            .get_inputs()
+            .is_empty();
+    if is_cacheable {
+        if let Some(component_return) = runtime.get_cached_template(id, &arg_values) {
+            let ctx = runtime.current_context()?;
+            let return_access =
+                DataAccess::new(&format!("{}_{}_{}", id, RETURN_VAR, generate_u32()), vec![]);
+            ctx.declare_item(DataType::Component, &return_access.get_name(), &[])?;
+            ctx.set_component(&return_access, component_return)?;
+
+            return Ok(return_access);
+        }
+    }
+    let cache_key = arg_values.clone();
+
+    // Functions only depend on their arguments, so their return values can be reused
+    if is_function {
+        if let Some((dimensions, values)) = runtime.get_cached_function(id, &arg_values) {
+            let ctx = runtime.current_context()?;
+            let return_access =
+                DataAccess::new(&format!("{}_{}_{}", id, RETURN_VAR, generate_u32()), vec![]);
+            ctx.declare_item(DataType::Variable, &return_access.get_name(), &dimensions)?;
+            set_variable_values(ctx, &return_access, &dimensions, values)?;
+
+            return Ok(return_access);
+        }
+    }
+
+    // Reuse the sub-circuit of the instance from a previous compilation, or record it to cache it
+    if !is_function {
+        if let Some(sub_circuit) = runtime.get_compiled_template(id, &arg_values) {
+            let component_return = sub_circuit.graft(ac)?;
+            let ctx = runtime.current_context()?;
+            let return_access =
+                DataAccess::new(&format!("{}_{}_{}", id, RETURN_VAR, generate_u32()), vec![]);
+            ctx.declare_item(DataType::Component, &return_access.get_name(), &[])?;
+            ctx.set_component(&return_access, component_return.clone())?;
+
+            if is_cacheable {
+                runtime.cache_template(id, &cache_key, component_return);
+            }
+            return Ok(return_access);
+        }
+        if runtime.has_compilation_cache() {
+            ac.start_recording();
+        }
+    }
+
+    // Create a new execution context
+    runtime.enter_call(id, config.max_recursion_depth)?;
+    runtime.push_context(false)?;
+
+    // Set arguments in the new context
+    for (arg_name, arg_value) in arg_names.iter().zip(arg_values) {
+        runtime
+            .current_context()?
+            .declare_item(DataType::Variable, arg_name, &[])?;
+        runtime
+            .current_context()?
+            .set_variable(&DataAccess::new(arg_name, vec![]), Some(arg_value))?;
+    }
+
+    // Process the function/template body
+    process_statements(ac, runtime, program_archive, config, body)?;
+
+    // Get return values
+    let mut function_return: FunctionReturn = (vec![], vec![None]);
+    let mut component_return: HashMap<String, Signal> = HashMap::new();
+
+    if is_function {
+        if let Ok(values) = get_variable_values(
+            runtime.current_context()?,
+            &DataAccess::new(RETURN_VAR, vec![]),
+        ) {
+            function_return = values;
+        }
+        runtime.cache_function(id, &cache_key, function_return.clone());
+    } else {
+        // Retrieve input and output signals
+        let template_data = program_archive.get_template_data(id);
+        let input_signals = template_data.get_inputs();
+        let output_signals = template_data.get_outputs();
+
+        // Store ids in the component
+        for (signal, _) in input_signals.iter().chain(output_signals.iter()) {
+            let ids = runtime.current_context()?.get_signal(signal)?;
+            component_return.insert(signal.to_string(), ids);
+        }
+    }
+
+    // Return to parent context
+    runtime.pop_context(false)?;
+    runtime.exit_call();
+    let ctx = runtime.current_context()?;
+    let return_access =
+        DataAccess::new(&format!("{}_{}_{}", id, RETURN_VAR, generate_u32()), vec![]);
+
+    if is_function {
+        let (dimensions, values) = function_return;
+        ctx.declare_item(DataType::Variable, &return_access.get_name(), &dimensions)?;
+        set_variable_values(ctx, &return_access, &dimensions, values)?;
+    } else {
+        ctx.declare_item(DataType::Component, &return_access.get_name(), &[])?;
+        ctx.set_component(&return_access, component_return.clone())?;
+    }
+
+    if !is_function && runtime.has_compilation_cache() {
+        let ops = ac.stop_recording();
+        if let Some(sub_circuit) = SubCircuit::new(ops, component_return.clone()) {
+            runtime.cache_compiled_template(id, &cache_key, sub_circuit);
+        }
+    }
+    if is_cacheable {
+        runtime.cache_template(id, &cache_key, component_return);
+    }
+
+    Ok(return_access)
+}
+
+/// Handles an anonymous component like `T(params)(signals)`.
+/// The template is instantiated as with a call and its inputs are connected to the given signals,
+/// either by name or in declaration order. If the template has a single output, an access to it is returned.
+#[allow(clippy::too_many_arguments)]
+fn handle_anonymous_component(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    id: &str,
+    is_parallel: bool,
+    params: &[Expression],
+    signals: &[Expression],
+    names: &Option<Vec<(AssignOp, String)>>,
+) -> Result<DataAccess, ProgramError> {
+    if !program_archive.contains_template(id) {
+        return Err(ProgramError::UndefinedFunctionOrTemplate { location: None });
+    }
+
+    let template_data = program_archive.get_template_data(id);
+    let input_names: Vec<String> = match names {
+        Some(names) => names.iter().map(|(_, name)| name.clone()).collect(),
+        None => template_data
+            .get_declaration_inputs()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect(),
+    };
+
+    if input_names.len() != signals.len() {
+        return Err(ProgramError::OperationError {
+            message: format!(
+                "Anonymous component {} expects {} inputs, got {}",
+                id,
+                input_names.len(),
+                signals.len()
+            ),
+            location: None,
+        });
+    }
+
+    let component_access = handle_call(ac, runtime, program_archive, config, id, params)?;
+    if is_parallel {
+        runtime
+            .current_context()?
+            .mark_parallel(&component_access)?;
+    }
+
+    // Connect the inputs
+    for (input_name, signal) in input_names.iter().zip(signals) {
+        let signal_access = process_expression(ac, runtime, program_archive, config, signal)?;
+        let input_access = DataAccess::new(
+            &component_access.get_name(),
+            vec![SubAccess::Component(input_name.clone())],
+        );
+        let first_rhe_gate = ac.gate_count();
+        handle_substitution(
+            ac,
+            runtime,
+            &input_access,
+            &AssignOp::AssignConstraintSignal,
+            &signal_access,
+            first_rhe_gate,
+        )?;
+    }
+
+    let outputs = template_data.get_declaration_outputs();
+    if outputs.len() == 1 {
+        return Ok(DataAccess::new(
+            &component_access.get_name(),
+            vec![SubAccess::Component(outputs[0].0.clone())],
+        ));
+    }
+
+    Ok(component_access)
+}
+
+/// Handles an inline array, storing its elements (possibly nested arrays) in a new array item.
+/// The array is a signal array if any of its elements is a signal, otherwise a variable array.
+fn handle_array_in_line(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    values: &[Expression],
+) -> Result<DataAccess, ProgramError> {
+    let element_accesses = values
+        .iter()
+        .map(|value| process_expression(ac, runtime, program_archive, config, value))
+        .collect::<Result<Vec<DataAccess>, ProgramError>>()?;
+
+    let ctx = runtime.current_context()?;
+
+    // Elements can be arrays themselves, in which case they must all have the same dimensions
+    let mut data_type = DataType::Variable;
+    let mut element_dimensions = None;
+    for element_access in &element_accesses {
+        let dimensions = ctx.get_item_dimensions(element_access)?;
+        match &element_dimensions {
+            Some(expected) if *expected != dimensions => {
+                return Err(ProgramError::OperationError {
+                    message: format!(
+                        "Inline array elements have different dimensions: {:?} and {:?}",
+                        expected, dimensions
+                    ),
+                    location: None,
+                });
+            }
+            Some(_) => {}
+            None => element_dimensions = Some(dimensions),
+        }
+
+        if ctx.get_item_data_type(&element_access.get_name())? != DataType::Variable {
+            data_type = DataType::Signal;
+        }
+    }
+
+    let mut dimensions = vec![element_accesses.len() as u32];
+    dimensions.extend(element_dimensions.unwrap_or_default());
+    let array_access = ctx.declare_random_array(data_type.clone(), &dimensions)?;
+    let op = if data_type == DataType::Signal {
+        add_signals(ac, ctx, &array_access, &dimensions, false)?;
+        AssignOp::AssignConstraintSignal
+    } else {
+        AssignOp::AssignVar
+    };
+
+    for (index, element_access) in element_accesses.iter().enumerate() {
+        let target_access = append_indices(&array_access, &[index as u32]);
+        let first_rhe_gate = ac.gate_count();
+        handle_substitution(
+            ac,
+            runtime,
+            &target_access,
+            &op,
+            element_access,
+            first_rhe_gate,
+        )?;
+    }
+
+    Ok(array_access)
+}
+
+/// Handles a uniform array like `[value; dimension]`, storing the value at every index of a new array item.
+/// Signal values are connected to every signal of a new signal array.
+fn handle_uniform_array(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    value: &Expression,
+    dimension: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    let dimension_access = process_expression(ac, runtime, program_archive, config, dimension)?;
+    let value_access = process_expression(ac, runtime, program_archive, config, value)?;
+
+    let ctx = runtime.current_context()?;
+    let length = get_u32_value(ctx, &dimension_access)?;
+    if length == 0 {
+        return Err(ProgramError::InvalidDimension);
+    }
+
+    let mut dimensions = vec![length];
+    dimensions.extend(ctx.get_item_dimensions(&value_access)?);
+
+    let (data_type, op) = match ctx.get_item_data_type(&value_access.get_name())? {
+        DataType::Variable => (DataType::Variable, AssignOp::AssignVar),
+        _ => (DataType::Signal, AssignOp::AssignConstraintSignal),
+    };
+    let array_access = ctx.declare_random_array(data_type.clone(), &dimensions)?;
+    if data_type == DataType::Signal {
+        add_signals(ac, ctx, &array_access, &dimensions, false)?;
+    }
+
+    for index in 0..length {
+        let target_access = append_indices(&array_access, &[index]);
+        let first_rhe_gate = ac.gate_count();
+        handle_substitution(
+            ac,
+            runtime,
+            &target_access,
+            &op,
+            &value_access,
+            first_rhe_gate,
+        )?;
+    }
+
+    Ok(array_access)
+}
+
+/// Handles a tuple, grouping its elements in a synthetic component keyed by their position.
+fn handle_tuple(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    values: &[Expression],
+) -> Result<DataAccess, ProgramError> {
+    let element_accesses = values
+        .iter()
+        .map(|value| process_expression(ac, runtime, program_archive, config, value))
+        .collect::<Result<Vec<DataAccess>, ProgramError>>()?;
+
+    let ctx = runtime.current_context()?;
+
+    let mut signal_map = HashMap::new();
+    for (index, element_access) in element_accesses.iter().enumerate() {
+        let signal = Signal::from_value(get_signal_ids(ac, ctx, element_access)?);
+        signal_map.insert(index.to_string(), signal);
+    }
+
+    let tuple_access = ctx.declare_random_item(DataType::Component)?;
+    ctx.set_component(&tuple_access, signal_map)?;
+
+    Ok(tuple_access)
+}
+
+/// Returns the signal ids of an item, with the same structure as the item.
+/// Variable values are added to the circuit as constants.
+fn get_signal_ids(
+    ac: &mut ArithmeticCircuit,
+    ctx: &Context,
+    access: &DataAccess,
+) -> Result<NestedValue<u32>, ProgramError> {
+    let dimensions = ctx.get_item_dimensions(access)?;
+    if dimensions.is_empty() {
+        return Ok(NestedValue::Value(get_signal_for_access(ac, ctx, access)?));
+    }
+
+    let values = (0..dimensions[0])
+        .map(|index| get_signal_ids(ac, ctx, &append_indices(access, &[index])))
+        .collect::<Result<Vec<NestedValue<u32>>, ProgramError>>()?;
+
+    Ok(NestedValue::Array(values))
+}
+
+/// Handles an infix operation.
+/// - If both inputs are variables, it directly computes the operation.
+/// - If one or both inputs are signals, it constructs the corresponding circuit gate.
+/// Returns the access to a variable containing the result of the operation or the signal of the output gate.
+/// Gates are annotated with the source location of the operation.
+#[allow(clippy::too_many_arguments)]
+fn handle_infix_op(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    meta: &Meta,
+    op: &ExpressionInfixOpcode,
+    lhe: &Expression,
+    rhe: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    let lhe_access = process_expression(ac, runtime, program_archive, config, lhe)?;
+    let rhe_access = process_expression(ac, runtime, program_archive, config, rhe)?;
+
+    let prime = runtime.get_prime().clone();
+    let ctx = runtime.current_context()?;
+
+    // Determine the data types of the left and right operands
+    let lhs_data_type = ctx.get_item_data_type(&lhe_access.get_name())?;
+    let rhs_data_type = ctx.get_item_data_type(&rhe_access.get_name())?;
+
+    // Handle the case where both inputs are variables
+    if lhs_data_type == DataType::Variable && rhs_data_type == DataType::Variable {
+        let lhs_value = ctx
+            .get_variable_value(&lhe_access)?
+            .ok_or(ProgramError::EmptyDataItem { location: None })?;
+        let rhs_value = ctx
+            .get_variable_value(&rhe_access)?
+            .ok_or(ProgramError::EmptyDataItem { location: None })?;
+
+        let op_res = execute_op(&lhs_value, &rhs_value, op, &prime)?;
+        let item_access = ctx.declare_random_item(DataType::Variable)?;
+        ctx.set_variable(&item_access, Some(op_res))?;
+
+        return Ok(item_access);
+    }
+
+    // A known zero divisor would build a gate that can never be satisfied
+    if rhs_data_type == DataType::Variable
+        && matches!(
+            op,
+            ExpressionInfixOpcode::Div | ExpressionInfixOpcode::IntDiv | ExpressionInfixOpcode::Mod
+        )
+    {
+        let rhs_value = ctx
+            .get_variable_value(&rhe_access)?
+            .ok_or(ProgramError::EmptyDataItem { location: None })?;
+        if rhs_value.is_zero() {
+            return Err(ProgramError::OperationError {
+                message: format!("Division by zero in {:?} operation on a signal", op),
+                location: None,
+            });
+        }
+    }
+
+    // Operations with an identity variable operand return the signal operand, and products by zero are zero
+    let get_value = |data_type: &DataType, access: &DataAccess| -> Result<_, ProgramError> {
+        Ok(match data_type {
+            DataType::Variable => ctx
+                .get_variable_value(access)?
+                .map(|value| modulo(&value, &prime)),
+            _ => None,
+        })
+    };
+    let lhs_value = get_value(&lhs_data_type, &lhe_access)?;
+    let rhs_value = get_value(&rhs_data_type, &rhe_access)?;
+    match (op, &lhs_value, &rhs_value) {
+        (ExpressionInfixOpcode::Mul, Some(value), _)
+        | (ExpressionInfixOpcode::Mul, _, Some(value))
+            if value.is_zero() =>
+        {
+            let item_access = ctx.declare_random_item(DataType::Variable)?;
+            ctx.set_variable(&item_access, Some(BigInt::zero()))?;
+            return Ok(item_access);
+        }
+        (ExpressionInfixOpcode::Add, Some(value), _) if value.is_zero() => return Ok(rhe_access),
+        (ExpressionInfixOpcode::Add | ExpressionInfixOpcode::Sub, _, Some(value))
+            if value.is_zero() =>
+        {
+            return Ok(lhe_access)
+        }
+        (ExpressionInfixOpcode::Mul, Some(value), _) if value.is_one() => return Ok(rhe_access),
+        (ExpressionInfixOpcode::Mul | ExpressionInfixOpcode::Div, _, Some(value))
+            if value.is_one() =>
+        {
+            return Ok(lhe_access)
+        }
+        _ => {}
+    }
+
+    // Handle cases where one or both inputs are signals
+    let lhs_id = get_signal_for_access(ac, ctx, &lhe_access)?;
+    let rhs_id = get_signal_for_access(ac, ctx, &rhe_access)?;
+
+    // Products with a variable operand are multiplications by a constant, which are linear
+    let gate_type = match op {
+        ExpressionInfixOpcode::Mul
+            if lhs_data_type == DataType::Variable || rhs_data_type == DataType::Variable =>
+        {
+            AGateType::ACMul
+        }
+        _ => AGateType::from(op),
+    };
+
+    // Construct the corresponding circuit gate
+    let output_signal = ctx.declare_random_item(DataType::Signal)?;
+    let output_id = ctx.get_signal_id(&output_signal)?;
+    ac.add_signal(output_id)?;
+    let gate_id = ac.add_gate(gate_type, lhs_id, Some(rhs_id), output_id)?;
+
+    if let Some(location) = SourceLocation::from_meta(meta, program_archive) {
+        ac.annotate_gate(
+            gate_id,
+            GateMeta {
+                source_file: location.file,
+                line: location.line,
+                col: location.col,
+                template_name: current_call_name(runtime, program_archive),
+            },
+        )?;
+    }
+
+    Ok(output_signal)
+}
+
+/// Returns the name of the function or template being processed, which is the main template outside of any call.
+fn current_call_name(runtime: &Runtime, program_archive: &ProgramArchive) -> String {
+    if let Some(name) = runtime.current_call() {
+        return name.to_string();
+    }
+
+    match program_archive.get_main_expression() {
+        Expression::Call { id, .. } => id.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Handles a prefix operation.
+/// - If the input is a variable, it directly computes the operation.
+/// - If the input is a signal, it constructs the equivalent circuit gate:
+///   `-x` becomes a negation gate and `!x` becomes `x == 0`.
+/// Returns the access to a variable containing the result of the operation or the signal of the output gate.
+fn handle_prefix_op(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    op: &ExpressionPrefixOpcode,
+    rhe: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    let rhe_access = process_expression(ac, runtime, program_archive, config, rhe)?;
+
+    let prime = runtime.get_prime().clone();
+    let ctx = runtime.current_context()?;
+
+    // Handle the case where the input is a variable
+    if ctx.get_item_data_type(&rhe_access.get_name())? == DataType::Variable {
+        let value = ctx
+            .get_variable_value(&rhe_access)?
+            .ok_or(ProgramError::EmptyDataItem { location: None })?;
+
+        let op_res = execute_prefix_op(&value, op, &prime)?;
+        let item_access = ctx.declare_random_item(DataType::Variable)?;
+        ctx.set_variable(&item_access, Some(op_res))?;
+
+        return Ok(item_access);
+    }
+
+    // Handle the case where the input is a signal
+    let rhs_id = get_signal_for_access(ac, ctx, &rhe_access)?;
+
+    match op {
+        ExpressionPrefixOpcode::Sub => add_gate_with_output(ac, ctx, AGateType::ANeg, rhs_id, None),
+        ExpressionPrefixOpcode::BoolNot => {
+            let zero_id = ac.add_const(BigInt::zero())?;
+            add_gate_with_output(ac, ctx, AGateType::AEq, rhs_id, Some(zero_id))
+        }
+        // Values are below the mask of the field bit size, so their complement is `mask - x`
+        ExpressionPrefixOpcode::Complement => {
+            let mask = (BigInt::one() << prime.bits()) - BigInt::one();
+            let mask_id = ac.add_const(modulo(&mask, &prime))?;
+            add_gate_with_output(ac, ctx, AGateType::ASub, mask_id, Some(rhs_id))
+        }
+    }
+}
+
+/// Handles an inline switch operation (`cond ? if_true : if_false`).
+/// - If the condition is a variable, only the selected branch is processed.
+/// - If the condition is a signal, both branches are processed and combined with a multiplexer
+///   computing `cond * (if_true - if_false) + if_false`.
+/// Returns the access to the selected branch result or the signal of the multiplexer output.
+fn handle_inline_switch_op(
+    ac: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    program_archive: &ProgramArchive,
+    config: &BuildConfig,
+    cond: &Expression,
+    if_true: &Expression,
+    if_false: &Expression,
+) -> Result<DataAccess, ProgramError> {
+    let cond_access = process_expression(ac, runtime, program_archive, config, cond)?;
+
+    // Handle the case where the condition is known at compile time
+    let ctx = runtime.current_context()?;
+    if ctx.get_item_data_type(&cond_access.get_name())? == DataType::Variable {
+        let cond_value = ctx
+            .get_variable_value(&cond_access)?
+            .ok_or(ProgramError::EmptyDataItem { location: None })?;
+
+        return if cond_value.is_zero() {
+            process_expression(ac, runtime, program_archive, config, if_false)
+        } else if cond_value.is_one() {
+            process_expression(ac, runtime, program_archive, config, if_true)
+        } else {
+            Err(ProgramError::ConstraintError(format!(
+                "Inline switch condition must evaluate to 0 or 1, got {}",
+                cond_value
+            )))
+        };
+    }
+
+    // Handle the case where the condition is a signal
+    let true_access = process_expression(ac, runtime, program_archive, config, if_true)?;
+    let false_access = process_expression(ac, runtime, program_archive, config, if_false)?;
+
+    let ctx = runtime.current_context()?;
+    let cond_id = get_signal_for_access(ac, ctx, &cond_access)?;
+    let true_id = get_signal_for_access(ac, ctx, &true_access)?;
+    let false_id = get_signal_for_access(ac, ctx, &false_access)?;
+
+    add_mux(ac, ctx, cond_id, true_id, false_id)
+}
+
+/// Adds the gates of a multiplexer computing `cond * (if_true - if_false) + if_false`, returning its output access.
+fn add_mux(
+    ac: &mut ArithmeticCircuit,
+    ctx: &mut Context,
+    cond_id: u32,
+    true_id: u32,
+    false_id: u32,
+) -> Result<DataAccess, ProgramError> {
+    let diff_access = add_gate_with_output(ac, ctx, AGateType::ASub, true_id, Some(false_id))?;
+    let diff_id = ctx.get_signal_id(&diff_access)?;
+    let product_access = add_gate_with_output(ac, ctx, AGateType::AMul, cond_id, Some(diff_id))?;
+    let product_id = ctx.get_signal_id(&product_access)?;
+
+    add_gate_with_output(ac, ctx, AGateType::AAdd, product_id, Some(false_id))
+}
+
+/// Declares a new output signal, adds it to the circuit with a gate of the given type and returns its access.
+/// Unary gates take no right-hand input.
+fn add_gate_with_output(
+    ac: &mut ArithmeticCircuit,
+    ctx: &mut Context,
+    gate_type: AGateType,
+    lhs_id: u32,
+    rhs_id: Option<u32>,
+) -> Result<DataAccess, ProgramError> {
+    let output_signal = ctx.declare_random_item(DataType::Signal)?;
+    let output_id = ctx.get_signal_id(&output_signal)?;
+
+    // Add output signal and gate to the circuit
+    ac.add_signal(output_id)?;
+    ac.add_gate(gate_type, lhs_id, rhs_id, output_id)?;
+
+    Ok(output_signal)
+}
+
+/// Returns a signal id for a given access
+/// - If the access is a signal or a component, it returns the corresponding signal id.
+/// - If the access is a variable, it adds a constant variable to the circuit and returns the corresponding signal id.
+fn get_signal_for_access(
+    ac: &mut ArithmeticCircuit,
+    ctx: &Context,
+    access: &DataAccess,
+) -> Result<u32, ProgramError> {
+    match ctx.get_item_data_type(&access.get_name())? {
+        DataType::Signal => Ok(ctx.get_signal_id(access)?),
+        DataType::Variable => {
+            let value = ctx
+                .get_variable_value(access)?
+                .ok_or(ProgramError::EmptyDataItem { location: None })?;
+            Ok(ac.add_const(value)?)
+        }
+        DataType::Component | DataType::Bus => Ok(ctx.get_component_signal_id(access)?),
+    }
+}
+
+/// Builds a DataAccess from an Access array
+pub fn build_access(
+    ac: &mut ArithmeticCircuit,
     runtime: &mut Runtime,
     program_archive: &ProgramArchive,
+    config: &BuildConfig,
     name: &str,
     access: &[Access],
 ) -> Result<DataAccess, ProgramError> {
@@ -483,11 +1711,29 @@ pub fn build_access(
     for a in access.iter() {
         match a {
             Access::ArrayAccess(expression) => {
-                let index_access = process_expression(ac, runtime, program_archive, expression)?;
-                let index = runtime
-                    .current_context()?
-                    .get_variable_value(&index_access)?
-                    .ok_or(ProgramError::EmptyDataItem)?;
+                let index_access =
+                    process_expression(ac, runtime, program_archive, config, expression)?;
+                let ctx = runtime.current_context()?;
+                let index = get_u32_value(ctx, &index_access)?;
+
+                // Check the index against the dimension it accesses, when it's known
+                let item_access = DataAccess::new(name, access_vec.clone());
+                if let Some(length) = ctx
+                    .get_item_dimensions(&item_access)
+                    .ok()
+                    .and_then(|dimensions| dimensions.first().copied())
+                {
+                    if index >= length {
+                        return Err(ProgramError::OperationError {
+                            message: format!(
+                                "Index {} out of bounds for {} with length {}",
+                                index, name, length
+                            ),
+                            location: None,
+                        });
+                    }
+                }
+
                 access_vec.push(SubAccess::Array(index));
             }
             Access::ComponentAccess(signal) => {
@@ -499,98 +1745,1335 @@ pub fn build_access(
     Ok(DataAccess::new(name, access_vec))
 }
 
-/// Executes an operation on two u32 values, performing the specified arithmetic or logical computation.
-pub fn execute_op(lhs: u32, rhs: u32, op: &ExpressionInfixOpcode) -> Result<u32, ProgramError> {
+/// Returns the value of a variable as a u32, as required for array indices and dimensions.
+fn get_u32_value(ctx: &Context, access: &DataAccess) -> Result<u32, ProgramError> {
+    let value = ctx
+        .get_variable_value(access)?
+        .ok_or(ProgramError::EmptyDataItem { location: None })?;
+
+    value.to_u32().ok_or(ProgramError::OperationError {
+        message: format!("Value {} does not fit in an u32", value),
+        location: None,
+    })
+}
+
+/// Executes an operation on two field elements, performing the specified arithmetic or logical computation.
+pub fn execute_op(
+    lhs: &BigInt,
+    rhs: &BigInt,
+    op: &ExpressionInfixOpcode,
+    prime: &BigInt,
+) -> Result<BigInt, ProgramError> {
     let res = match op {
         ExpressionInfixOpcode::Mul => lhs * rhs,
         ExpressionInfixOpcode::Div => {
-            if rhs == 0 {
-                return Err(ProgramError::OperationError("Division by zero".to_string()));
+            if rhs.is_zero() {
+                return Err(ProgramError::OperationError {
+                    message: "Division by zero".to_string(),
+                    location: None,
+                });
             }
 
-            lhs / rhs
+            lhs * modular_inverse(rhs, prime)
         }
         ExpressionInfixOpcode::Add => lhs + rhs,
         ExpressionInfixOpcode::Sub => lhs - rhs,
-        ExpressionInfixOpcode::Pow => lhs.pow(rhs),
+        // Square-and-multiply on the field representatives, the exponent being non negative like any field element
+        ExpressionInfixOpcode::Pow => modulo(lhs, prime).modpow(&modulo(rhs, prime), prime),
         ExpressionInfixOpcode::IntDiv => {
-            if rhs == 0 {
-                return Err(ProgramError::OperationError(
-                    "Integer division by zero".to_string(),
-                ));
+            if rhs.is_zero() {
+                return Err(ProgramError::OperationError {
+                    message: "Integer division by zero".to_string(),
+                    location: None,
+                });
             }
 
             lhs / rhs
         }
         ExpressionInfixOpcode::Mod => {
-            if rhs == 0 {
-                return Err(ProgramError::OperationError("Modulo by zero".to_string()));
+            if rhs.is_zero() {
+                return Err(ProgramError::OperationError {
+                    message: "Modulo by zero".to_string(),
+                    location: None,
+                });
             }
 
             lhs % rhs
         }
-        ExpressionInfixOpcode::ShiftL => lhs << rhs,
-        ExpressionInfixOpcode::ShiftR => lhs >> rhs,
+        ExpressionInfixOpcode::ShiftL => shift(lhs, rhs, true, prime)?,
+        ExpressionInfixOpcode::ShiftR => shift(lhs, rhs, false, prime)?,
         ExpressionInfixOpcode::LesserEq => {
-            if lhs <= rhs {
-                1
-            } else {
-                0
-            }
+            bool_to_field(to_signed(lhs, prime) <= to_signed(rhs, prime))
         }
         ExpressionInfixOpcode::GreaterEq => {
-            if lhs >= rhs {
-                1
-            } else {
-                0
-            }
+            bool_to_field(to_signed(lhs, prime) >= to_signed(rhs, prime))
         }
         ExpressionInfixOpcode::Lesser => {
-            if lhs < rhs {
-                1
-            } else {
-                0
-            }
+            bool_to_field(to_signed(lhs, prime) < to_signed(rhs, prime))
         }
         ExpressionInfixOpcode::Greater => {
-            if lhs > rhs {
-                1
-            } else {
-                0
+            bool_to_field(to_signed(lhs, prime) > to_signed(rhs, prime))
+        }
+        ExpressionInfixOpcode::Eq => bool_to_field(lhs == rhs),
+        ExpressionInfixOpcode::NotEq => bool_to_field(lhs != rhs),
+        ExpressionInfixOpcode::BoolOr => bool_to_field(!lhs.is_zero() || !rhs.is_zero()),
+        ExpressionInfixOpcode::BoolAnd => bool_to_field(!lhs.is_zero() && !rhs.is_zero()),
+        ExpressionInfixOpcode::BitOr => lhs.clone() | rhs.clone(),
+        ExpressionInfixOpcode::BitAnd => lhs.clone() & rhs.clone(),
+        ExpressionInfixOpcode::BitXor => lhs.clone() ^ rhs.clone(),
+    };
+
+    Ok(modulo(&res, prime))
+}
+
+/// Executes a prefix operation on a field element.
+pub fn execute_prefix_op(
+    value: &BigInt,
+    op: &ExpressionPrefixOpcode,
+    prime: &BigInt,
+) -> Result<BigInt, ProgramError> {
+    let res = match op {
+        ExpressionPrefixOpcode::Sub => prime - value,
+        ExpressionPrefixOpcode::BoolNot => bool_to_field(value.is_zero()),
+        ExpressionPrefixOpcode::Complement => {
+            // Complement within the bit size of the field
+            let mask = (BigInt::one() << prime.bits()) - BigInt::one();
+            value.clone() ^ mask
+        }
+    };
+
+    Ok(modulo(&res, prime))
+}
+
+/// Reduces a value to its canonical representative in the field.
+pub fn modulo(value: &BigInt, prime: &BigInt) -> BigInt {
+    ((value % prime) + prime) % prime
+}
+
+/// Computes the multiplicative inverse of a non-zero field element using Fermat's little theorem.
+fn modular_inverse(value: &BigInt, prime: &BigInt) -> BigInt {
+    value.modpow(&(prime - BigInt::from(2u32)), prime)
+}
+
+/// Maps a field element to its signed representative, where values above `prime / 2` are negative.
+fn to_signed(value: &BigInt, prime: &BigInt) -> BigInt {
+    if value > &(prime >> 1usize) {
+        value - prime
+    } else {
+        value.clone()
+    }
+}
+
+/// Shifts a field element. A shift by a negative amount shifts in the opposite direction.
+fn shift(
+    value: &BigInt,
+    amount: &BigInt,
+    left: bool,
+    prime: &BigInt,
+) -> Result<BigInt, ProgramError> {
+    let signed_amount = to_signed(amount, prime);
+    let (left, amount) = if signed_amount < BigInt::zero() {
+        (!left, -signed_amount)
+    } else {
+        (left, signed_amount)
+    };
+
+    let amount = amount
+        .to_usize()
+        .filter(|amount| *amount <= prime.bits())
+        .ok_or(ProgramError::OperationError {
+            message: format!("Shift amount {} exceeds the field size", amount),
+            location: None,
+        })?;
+
+    Ok(if left {
+        value << amount
+    } else {
+        value >> amount
+    })
+}
+
+/// Converts a boolean into its field element representation.
+fn bool_to_field(value: bool) -> BigInt {
+    if value {
+        BigInt::one()
+    } else {
+        BigInt::zero()
+    }
+}
+
+/// Returns the kind of a statement, for tracing.
+fn statement_kind(statement: &Statement) -> &'static str {
+    match statement {
+        Statement::Assert { .. } => "assert",
+        Statement::Block { .. } => "block",
+        Statement::ConstraintEquality { .. } => "constraint equality",
+        Statement::Declaration { .. } => "declaration",
+        Statement::IfThenElse { .. } => "if-then-else",
+        Statement::InitializationBlock { .. } => "initialization block",
+        Statement::LogCall { .. } => "log call",
+        Statement::MultSubstitution { .. } => "multiple substitution",
+        Statement::Return { .. } => "return",
+        Statement::Substitution { .. } => "substitution",
+        Statement::UnderscoreSubstitution { .. } => "underscore substitution",
+        Statement::While { .. } => "while",
+    }
+}
+
+/// Returns the kind of an expression, for tracing.
+fn expression_kind(expression: &Expression) -> &'static str {
+    match expression {
+        Expression::AnonymousComp { .. } => "anonymous component",
+        Expression::ArrayInLine { .. } => "inline array",
+        Expression::Call { .. } => "call",
+        Expression::InfixOp { .. } => "infix operation",
+        Expression::InlineSwitchOp { .. } => "inline switch",
+        Expression::Number(..) => "number",
+        Expression::ParallelOp { .. } => "parallel operation",
+        Expression::PrefixOp { .. } => "prefix operation",
+        Expression::Tuple { .. } => "tuple",
+        Expression::UniformArray { .. } => "uniform array",
+        Expression::Variable { .. } => "variable",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::{test_utils::bn254_prime, CircuitError},
+        compiler::{compile_circom_source, compile_circom_source_with_config},
+    };
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use std::{cell::RefCell, io, rc::Rc, sync::Mutex};
+
+    /// Log writer sharing its output with the test.
+    #[derive(Clone, Default)]
+    struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Compiles a template and simulates it, returning the value of its `out` output for the given inputs.
+    fn evaluate(source: &str, main_component: &str, inputs: &[(&str, i64)]) -> BigInt {
+        try_evaluate(source, main_component, inputs).unwrap()
+    }
+
+    /// Compiles a program with the circom pragma, returning its circuit or the compilation error.
+    fn compile(source: &str, main_component: &str) -> Result<ArithmeticCircuit, ProgramError> {
+        compile_circom_source(&format!("pragma circom 2.1.6;\n{}", source), main_component)
+    }
+
+    /// Compiles a template and simulates it, returning the value of its `out` output or the simulation error.
+    fn try_evaluate(
+        source: &str,
+        main_component: &str,
+        inputs: &[(&str, i64)],
+    ) -> Result<BigInt, CircuitError> {
+        try_evaluate_outputs(source, main_component, inputs).map(|outputs| outputs["out"].clone())
+    }
+
+    /// Compiles a template and simulates it, returning the values of its outputs by name or the simulation error.
+    fn try_evaluate_outputs(
+        source: &str,
+        main_component: &str,
+        inputs: &[(&str, i64)],
+    ) -> Result<HashMap<String, BigInt>, CircuitError> {
+        let circuit = compile(source, main_component).unwrap();
+        let ids = circuit.public_inputs();
+        let inputs = inputs
+            .iter()
+            .map(|(name, value)| (ids[*name], BigInt::from(*value)))
+            .collect();
+        let values = circuit.simulate(&inputs, &bn254_prime())?;
+
+        Ok(circuit
+            .public_outputs()
+            .into_iter()
+            .map(|(name, id)| (name, values[&id].clone()))
+            .collect())
+    }
+
+    #[test]
+    fn prefix_ops_on_signals() {
+        let template = |op: &str| {
+            format!(
+                "template T() {{ signal input a; signal output out; out <== {}a; }}",
+                op
+            )
+        };
+
+        assert_eq!(
+            evaluate(&template("-"), "T()", &[("a", 5)]),
+            bn254_prime() - BigInt::from(5)
+        );
+        assert_eq!(evaluate(&template("!"), "T()", &[("a", 5)]), BigInt::zero());
+        assert_eq!(evaluate(&template("!"), "T()", &[("a", 0)]), BigInt::one());
+        assert_eq!(
+            evaluate(&template("~"), "T()", &[("a", 5)]),
+            execute_prefix_op(
+                &BigInt::from(5),
+                &ExpressionPrefixOpcode::Complement,
+                &bn254_prime()
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn signal_if_then_else_multiplexes_branch_expressions() {
+        let source = "
+            template Select() {
+                signal input c;
+                signal input a;
+                signal output out;
+                if (c) {
+                    out <== a + 1;
+                } else {
+                    out <== a * 2;
+                }
+            }";
+
+        assert_eq!(
+            evaluate(source, "Select()", &[("c", 1), ("a", 3)]),
+            BigInt::from(4)
+        );
+        assert_eq!(
+            evaluate(source, "Select()", &[("c", 0), ("a", 3)]),
+            BigInt::from(6)
+        );
+    }
+
+    #[test]
+    fn nested_inline_switches() {
+        let source = "
+            template T() {
+                signal input zero;
+                signal input neg;
+                signal input a;
+                signal output out;
+                var k = 2;
+                out <== zero ? 0 : (neg ? -a : a * (k == 2 ? 3 : 5));
+            }";
+
+        assert_eq!(
+            evaluate(source, "T()", &[("zero", 0), ("neg", 0), ("a", 4)]),
+            BigInt::from(12)
+        );
+        assert_eq!(
+            evaluate(source, "T()", &[("zero", 0), ("neg", 1), ("a", 4)]),
+            bn254_prime() - BigInt::from(4)
+        );
+        assert_eq!(
+            evaluate(source, "T()", &[("zero", 1), ("neg", 1), ("a", 4)]),
+            BigInt::zero()
+        );
+    }
+
+    #[test]
+    fn prefix_ops_on_values() {
+        let prime = bn254_prime();
+        let negate = |value: i64| {
+            execute_prefix_op(&BigInt::from(value), &ExpressionPrefixOpcode::Sub, &prime).unwrap()
+        };
+        let not = |value: i64| {
+            execute_prefix_op(
+                &BigInt::from(value),
+                &ExpressionPrefixOpcode::BoolNot,
+                &prime,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(negate(3), &prime - BigInt::from(3));
+        assert_eq!(negate(0), BigInt::zero());
+        assert_eq!(not(0), BigInt::one());
+        assert_eq!(not(7), BigInt::zero());
+
+        let source = "
+            template T() {
+                signal input a;
+                signal output out;
+                var x = -3;
+                out <== a + x + !0 + !x;
+            }";
+        assert_eq!(evaluate(source, "T()", &[("a", 10)]), BigInt::from(8));
+    }
+
+    #[test]
+    fn constraint_equalities_are_checked() {
+        let source = "
+            template T() {
+                signal input a;
+                signal input b;
+                signal output out;
+                a * 2 === b;
+                out <== a + b;
+            }";
+
+        assert_eq!(
+            evaluate(source, "T()", &[("a", 2), ("b", 4)]),
+            BigInt::from(6)
+        );
+        assert!(matches!(
+            try_evaluate(source, "T()", &[("a", 2), ("b", 5)]),
+            Err(CircuitError::ConstraintNotSatisfied(_))
+        ));
+    }
+
+    #[test]
+    fn logs_go_to_the_configured_writer() {
+        let source = "pragma circom 2.1.6;
+            template T() {
+                signal input a;
+                signal output out;
+                var x = 3;
+                log(\"x is\", x * 2, \"then\", x);
+                out <== a * x;
+            }";
+        let writer = SharedWriter::default();
+        let config = BuildConfig {
+            log_writer: Some(Box::new(writer.clone())),
+            ..BuildConfig::default()
+        };
+        compile_circom_source_with_config(source, "T()", config).unwrap();
+
+        assert_eq!(
+            String::from_utf8(writer.0.borrow().clone()).unwrap(),
+            "x is 6 then 3\n"
+        );
+    }
+
+    #[test]
+    fn multiple_substitution_from_component_outputs() {
+        let source = "
+            template SumProd() {
+                signal input a;
+                signal input b;
+                signal output s;
+                signal output p;
+                s <== a + b;
+                p <== a * b;
+            }
+            template T() {
+                signal input a;
+                signal input b;
+                signal output out;
+                signal s;
+                signal p;
+                (s, p) <== SumProd()(a, b);
+                out <== s * 100 + p;
+            }";
+
+        assert_eq!(
+            evaluate(source, "T()", &[("a", 3), ("b", 4)]),
+            BigInt::from(712)
+        );
+    }
+
+    #[test]
+    fn infix_ops_wrap_around_the_prime() {
+        let prime = bn254_prime();
+        let minus_one = &prime - BigInt::one();
+        let execute = |lhs: &BigInt, op: ExpressionInfixOpcode, rhs: &BigInt| {
+            execute_op(lhs, rhs, &op, &prime).unwrap()
+        };
+
+        assert_eq!(
+            execute(
+                &BigInt::from(2),
+                ExpressionInfixOpcode::Sub,
+                &BigInt::from(3)
+            ),
+            minus_one
+        );
+        assert_eq!(
+            execute(&minus_one, ExpressionInfixOpcode::Add, &BigInt::from(2)),
+            BigInt::one()
+        );
+        assert_eq!(
+            execute(&minus_one, ExpressionInfixOpcode::Mul, &minus_one),
+            BigInt::one()
+        );
+        assert_eq!(
+            execute(&BigInt::one(), ExpressionInfixOpcode::Div, &minus_one),
+            minus_one
+        );
+    }
+
+    #[test]
+    fn variables_are_computed_in_the_configured_field() {
+        let source = "pragma circom 2.1.6;
+            template T() {
+                signal input a;
+                signal output out;
+                var x = 16 + 4;
+                out <== a * (x \\ 2);
+            }";
+        let prime = BigInt::from(17);
+        let config = BuildConfig {
+            field_prime: prime.clone(),
+            ..BuildConfig::default()
+        };
+        let circuit = compile_circom_source_with_config(source, "T()", config).unwrap();
+        let inputs = HashMap::from([(circuit.public_inputs()["a"], BigInt::from(2))]);
+        let values = circuit.simulate(&inputs, &prime).unwrap();
+
+        // 20 is 3 in the field, so the integer division gives 1
+        assert_eq!(values[&circuit.public_outputs()["out"]], BigInt::from(2));
+    }
+
+    #[test]
+    fn large_results_are_reduced() {
+        let prime = bn254_prime();
+        let minus_one = &prime - BigInt::one();
+        let two = BigInt::from(2);
+
+        assert_eq!(
+            execute_op(&minus_one, &minus_one, &ExpressionInfixOpcode::Pow, &prime).unwrap(),
+            BigInt::one()
+        );
+        assert_eq!(
+            execute_op(
+                &two,
+                &BigInt::from(300),
+                &ExpressionInfixOpcode::Pow,
+                &prime
+            )
+            .unwrap(),
+            (BigInt::one() << 300usize) % &prime
+        );
+
+        let source = "
+            template T() {
+                signal input a;
+                signal output out;
+                var x = 2 ** 300 * 2 ** 300 + 2 ** 300;
+                out <== a + x;
+            }";
+        let x = ((BigInt::one() << 600usize) + (BigInt::one() << 300usize)) % &prime;
+        assert_eq!(evaluate(source, "T()", &[("a", 0)]), x);
+    }
+
+    #[test]
+    fn inline_arrays() {
+        let source = "
+            template T() {
+                signal input a;
+                signal output out;
+                var x[3] = [1, 2, 3];
+                var y[2][2] = [[1, 2], [3, 4]];
+                out <== a * x[1] + y[1][0] * x[2];
+            }";
+
+        assert_eq!(evaluate(source, "T()", &[("a", 5)]), BigInt::from(19));
+    }
+
+    #[test]
+    fn uniform_arrays() {
+        // Variable arrays declared without a value are uniform arrays of zeros
+        let source = "
+            template T(n) {
+                signal input a;
+                signal output out;
+                var x[5];
+                var y[n][2];
+                x[2] = 7;
+                y[n - 1][1] = 3;
+                out <== a * y[n - 1][1] + x[0] + x[2] + x[4] + y[0][0];
+            }";
+
+        assert_eq!(evaluate(source, "T(4)", &[("a", 5)]), BigInt::from(22));
+    }
+
+    #[test]
+    fn whole_signal_arrays_are_connected() {
+        let source = "
+            template T() {
+                signal input in[4];
+                signal input m[2][2];
+                signal output out[4];
+                signal output n[2][2];
+                out <== in;
+                n <== m;
+            }";
+        let inputs = [
+            ("in[0]", 1),
+            ("in[1]", 2),
+            ("in[2]", 3),
+            ("in[3]", 4),
+            ("m[0][0]", 5),
+            ("m[0][1]", 6),
+            ("m[1][0]", 7),
+            ("m[1][1]", 8),
+        ];
+        let outputs = try_evaluate_outputs(source, "T()", &inputs).unwrap();
+
+        for index in 0..4 {
+            assert_eq!(outputs[&format!("out[{}]", index)], BigInt::from(index + 1));
+            assert_eq!(
+                outputs[&format!("n[{}][{}]", index / 2, index % 2)],
+                BigInt::from(index + 5)
+            );
+        }
+    }
+
+    #[test]
+    fn signal_division_by_a_known_zero_fails() {
+        let literal = "
+            template T() {
+                signal input a;
+                signal output out;
+                out <== a / 0;
+            }";
+        let parameter = "
+            template T(n) {
+                signal input a;
+                signal output out;
+                out <== a / (n - 2);
+            }";
+
+        assert!(matches!(
+            compile(literal, "T()"),
+            Err(ProgramError::OperationError { .. })
+        ));
+        assert!(matches!(
+            compile(parameter, "T(2)"),
+            Err(ProgramError::OperationError { .. })
+        ));
+        assert_eq!(evaluate(parameter, "T(4)", &[("a", 6)]), BigInt::from(3));
+    }
+
+    #[test]
+    fn bus_fields_are_signals() {
+        let source = "pragma circom 2.2.0;
+            bus Point() {
+                signal x;
+                signal y;
+            }
+            template T() {
+                signal input a;
+                signal output out;
+                Point() p;
+                p.x <== a + 1;
+                p.y <== a * 2;
+                out <== p.x * p.y;
+            }";
+        let circuit = compile_circom_source(source, "T()").unwrap();
+        let inputs = HashMap::from([(circuit.public_inputs()["a"], BigInt::from(3))]);
+        let values = circuit.simulate(&inputs, &bn254_prime()).unwrap();
+
+        assert_eq!(values[&circuit.public_outputs()["out"]], BigInt::from(24));
+    }
+
+    #[test]
+    fn tuples_are_assigned_element_wise() {
+        let source = "
+            template T() {
+                signal input a;
+                signal output out;
+                signal x;
+                signal y;
+                (x, y) <== (a + 1, a * 2);
+                out <== x * y;
+            }";
+
+        assert_eq!(evaluate(source, "T()", &[("a", 3)]), BigInt::from(24));
+    }
+
+    #[test]
+    fn multiple_substitution_is_parallel() {
+        let source = "
+            template T() {
+                signal input a;
+                signal output out;
+                var x = 1;
+                var y = 2;
+                (x, y) = (y, x);
+                out <== a * x + y;
+            }";
+
+        assert_eq!(evaluate(source, "T()", &[("a", 5)]), BigInt::from(11));
+    }
+
+    #[test]
+    fn anonymous_components() {
+        let source = "
+            template Sub() {
+                signal input a;
+                signal input b;
+                signal output out;
+                out <== a - b;
             }
+            template T() {
+                signal input x;
+                signal input y;
+                signal output out;
+                signal d;
+                d <== Sub()(x, y);
+                out <== d * 10 + Sub()(b <== x, a <== y);
+            }";
+
+        assert_eq!(
+            evaluate(source, "T()", &[("x", 5), ("y", 2)]),
+            BigInt::from(27)
+        );
+    }
+
+    #[test]
+    fn unconstrained_outputs_are_flagged() {
+        let source = "pragma circom 2.1.6;
+            template T() {
+                signal input a;
+                signal output out;
+                signal output forgotten;
+                out <== a * 2;
+            }";
+        let circuit = compile_circom_source(source, "T()").unwrap();
+
+        assert_eq!(
+            circuit.check_outputs_constrained().unwrap(),
+            vec![circuit.public_outputs()["forgotten"]]
+        );
+
+        let config = BuildConfig {
+            strict_outputs: true,
+            ..BuildConfig::default()
+        };
+        assert!(matches!(
+            compile_circom_source_with_config(source, "T()", config),
+            Err(ProgramError::UnconstrainedOutputs(_))
+        ));
+    }
+
+    #[test]
+    fn field_and_integer_divisions_differ() {
+        let prime = bn254_prime();
+        let (seven, two) = (BigInt::from(7), BigInt::from(2));
+        let divide = |op: ExpressionInfixOpcode, lhs: &BigInt, rhs: &BigInt| {
+            execute_op(lhs, rhs, &op, &prime)
+        };
+
+        let quotient = divide(ExpressionInfixOpcode::Div, &seven, &two).unwrap();
+        assert_eq!(modulo(&(&quotient * &two), &prime), seven);
+        assert_eq!(quotient, (&prime + &seven) / &two);
+        assert_eq!(
+            divide(ExpressionInfixOpcode::IntDiv, &seven, &two).unwrap(),
+            BigInt::from(3)
+        );
+        assert_eq!(
+            divide(ExpressionInfixOpcode::Mod, &seven, &two).unwrap(),
+            BigInt::one()
+        );
+        for op in [
+            ExpressionInfixOpcode::Div,
+            ExpressionInfixOpcode::IntDiv,
+            ExpressionInfixOpcode::Mod,
+        ] {
+            assert!(matches!(
+                divide(op, &seven, &BigInt::zero()),
+                Err(ProgramError::OperationError { .. })
+            ));
         }
-        ExpressionInfixOpcode::Eq => {
-            if lhs == rhs {
-                1
-            } else {
-                0
+
+        let minus_one = &prime - BigInt::one();
+        assert_eq!(modular_inverse(&BigInt::one(), &prime), BigInt::one());
+        assert_eq!(modular_inverse(&minus_one, &prime), minus_one);
+    }
+
+    #[test]
+    fn endless_loops_hit_the_loop_limit() {
+        let source = "pragma circom 2.1.6;
+            template T() {
+                signal input a;
+                signal output out;
+                var i = 0;
+                while (i >= 0) {
+                    i = i + 1;
+                }
+                out <== a * i;
+            }";
+        let config = BuildConfig {
+            loop_limit: 100,
+            ..BuildConfig::default()
+        };
+
+        match compile_circom_source_with_config(source, "T()", config) {
+            Err(ProgramError::OperationError { message, .. }) => {
+                assert!(message.contains("loop limit exceeded"))
             }
+            result => panic!("expected a loop limit error, got {:?}", result.err()),
         }
-        ExpressionInfixOpcode::NotEq => {
-            if lhs != rhs {
-                1
-            } else {
-                0
+    }
+
+    #[test]
+    fn signal_if_then_else_requires_matching_branches() {
+        let two_signals = "
+            template T() {
+                signal input c;
+                signal input a;
+                signal output out;
+                signal x;
+                if (c) {
+                    x <== a;
+                    out <== a * a;
+                } else {
+                    x <== 1;
+                    out <== a + a;
+                }
+            }";
+        let without_else = "
+            template T() {
+                signal input c;
+                signal input a;
+                signal output out;
+                if (c) {
+                    out <== a;
+                }
+            }";
+        let one_sided = "
+            template T() {
+                signal input c;
+                signal input a;
+                signal output out;
+                signal x;
+                if (c) {
+                    out <== a;
+                } else {
+                    x <== a;
+                }
+            }";
+
+        assert_eq!(
+            evaluate(two_signals, "T()", &[("c", 1), ("a", 3)]),
+            BigInt::from(9)
+        );
+        assert_eq!(
+            evaluate(two_signals, "T()", &[("c", 0), ("a", 3)]),
+            BigInt::from(6)
+        );
+        assert!(matches!(
+            compile(without_else, "T()"),
+            Err(ProgramError::OperationError { .. })
+        ));
+        assert!(matches!(
+            compile(one_sided, "T()"),
+            Err(ProgramError::OperationError { .. })
+        ));
+    }
+
+    #[test]
+    fn signal_comparisons_are_boolean() {
+        let source = "
+            template T() {
+                signal input a;
+                signal input b;
+                signal output out;
+                out <== a < b;
+            }";
+
+        for (a, b, expected) in [(3, 5, 1), (5, 3, 0), (4, 4, 0), (-1, 2, 1)] {
+            assert_eq!(
+                evaluate(source, "T()", &[("a", a), ("b", b)]),
+                BigInt::from(expected)
+            );
+        }
+
+        // Lowered comparisons only use field operations, so the circuit can be exported to R1CS
+        let mut circuit = compile(source, "T()").unwrap();
+        assert_eq!(circuit.lower_comparisons(8).unwrap(), 1);
+        assert!(circuit
+            .gates_iter()
+            .all(|gate| gate.get_gate_type() != &AGateType::ALt));
+        assert!(circuit.to_r1cs(&bn254_prime()).is_ok());
+    }
+
+    #[test]
+    fn parallel_components_are_regular_components() {
+        let template = |call: &str| {
+            format!(
+                "
+                template Square() {{
+                    signal input in;
+                    signal output out;
+                    out <== in * in;
+                }}
+                template T() {{
+                    signal input a;
+                    signal output out;
+                    out <== {}(a) + 1;
+                }}",
+                call
+            )
+        };
+        let parallel = template("parallel Square()");
+        let sequential = template("Square()");
+
+        assert_eq!(evaluate(&parallel, "T()", &[("a", 3)]), BigInt::from(10));
+        assert!(compile(&parallel, "T()")
+            .unwrap()
+            .is_isomorphic_to(&compile(&sequential, "T()").unwrap()));
+    }
+
+    #[test]
+    fn errors_point_at_their_statement() {
+        // The pragma is the first line
+        let source = "template T() {
+    signal input a;
+    signal output out;
+    out <== a;
+    out <== a * 2;
+}";
+
+        match compile(source, "T()") {
+            Err(ProgramError::SignalAlreadyAssigned {
+                location: Some(location),
+                ..
+            }) => {
+                assert_eq!((location.line, location.col), (6, 5));
+                assert!(location.file.ends_with(".circom"));
             }
+            result => panic!("expected a located error, got {:?}", result.err()),
         }
-        ExpressionInfixOpcode::BoolOr => {
-            if lhs != 0 || rhs != 0 {
-                1
-            } else {
-                0
+    }
+
+    #[test]
+    fn equal_constants_share_a_signal() {
+        let source = "
+            template T() {
+                signal input a;
+                signal input b;
+                signal output out;
+                signal c;
+                c <== a + 1;
+                out <== (b + 1) * c + 1 - 1 + 1;
+            }";
+        let circuit = compile(source, "T()").unwrap();
+
+        assert_eq!(circuit.stats().constant_count, 1);
+        assert_eq!(
+            evaluate(source, "T()", &[("a", 2), ("b", 3)]),
+            BigInt::from(13)
+        );
+    }
+
+    #[test]
+    fn out_of_bounds_accesses_fail() {
+        let read = "
+            template T() {
+                signal input a[3];
+                signal output out;
+                out <== a[5];
+            }";
+        let write = "
+            template T() {
+                signal input a;
+                signal output out[3];
+                var i = 3;
+                out[i] <== a;
+            }";
+
+        for source in [read, write] {
+            match compile(source, "T()") {
+                Err(ProgramError::OperationError { message, .. }) => {
+                    assert!(message.contains("out of bounds"), "{}", message)
+                }
+                result => panic!("expected a bounds error, got {:?}", result.err()),
             }
         }
-        ExpressionInfixOpcode::BoolAnd => {
-            if lhs != 0 && rhs != 0 {
-                1
-            } else {
-                0
+    }
+
+    #[test]
+    fn repeated_template_calls() {
+        let source = "
+            template Inc() {
+                signal input in;
+                signal output out;
+                out <== in + 1;
+            }
+            template T(n) {
+                signal input a;
+                signal output out;
+                signal chain[n + 1];
+                chain[0] <== a;
+                for (var i = 0; i < n; i++) {
+                    chain[i + 1] <== Inc()(chain[i]);
+                }
+                out <== chain[n];
+            }";
+
+        assert_eq!(compile(source, "T(1000)").unwrap().gate_count(), 1000);
+        assert_eq!(evaluate(source, "T(1000)", &[("a", 5)]), BigInt::from(1005));
+    }
+
+    #[test]
+    fn identity_operands_add_no_gate() {
+        let template = |expression: &str| {
+            format!(
+                "template T() {{ signal input a; signal output out; out <== {}; }}",
+                expression
+            )
+        };
+
+        for (expression, expected) in [("a + 0", 7), ("0 + a", 7), ("a * 1", 7), ("a * 0", 0)] {
+            let source = template(expression);
+            assert_eq!(compile(&source, "T()").unwrap().gate_count(), 0);
+            assert_eq!(
+                evaluate(&source, "T()", &[("a", 7)]),
+                BigInt::from(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn functions_return_arrays() {
+        let source = "
+            function pair(x) {
+                var r[2];
+                r[0] = x;
+                r[1] = x * 2;
+                return r;
+            }
+            function grid() {
+                return [[1, 2], [3, 4]];
+            }
+            template T() {
+                signal input a;
+                signal output out;
+                var p[2] = pair(3);
+                var g[2][2] = grid();
+                out <== a * p[1] + p[0] + g[1][0];
+            }";
+
+        assert_eq!(evaluate(source, "T()", &[("a", 2)]), BigInt::from(18));
+    }
+
+    #[test]
+    fn public_signals_are_named() {
+        let source = "
+            template T() {
+                signal input in[2];
+                signal input k;
+                signal output out;
+                signal inner;
+                inner <== in[0] - in[1];
+                out <== inner * k;
+            }";
+        let circuit = compile(source, "T()").unwrap();
+        let mut input_names: Vec<String> = circuit.public_inputs().into_keys().collect();
+        input_names.sort();
+
+        assert_eq!(input_names, ["in[0]", "in[1]", "k"]);
+        assert_eq!(
+            circuit.public_outputs(),
+            HashMap::from([("out".to_string(), circuit.get_outputs()[0])])
+        );
+        assert_eq!(
+            evaluate(source, "T()", &[("in[0]", 5), ("in[1]", 2), ("k", 10)]),
+            BigInt::from(30)
+        );
+    }
+
+    #[test]
+    fn powers_use_modular_exponentiation() {
+        let prime = bn254_prime();
+        let pow = |base: &BigInt, exponent: &BigInt| {
+            execute_op(base, exponent, &ExpressionInfixOpcode::Pow, &prime).unwrap()
+        };
+
+        for base in [BigInt::zero(), BigInt::from(5), &prime - BigInt::one()] {
+            assert_eq!(pow(&base, &BigInt::zero()), BigInt::one());
+        }
+        assert_eq!(
+            pow(&BigInt::from(2), &BigInt::from(300)),
+            (BigInt::one() << 300usize) % &prime
+        );
+        // Fermat's little theorem
+        assert_eq!(
+            pow(&BigInt::from(3), &(&prime - BigInt::one())),
+            BigInt::one()
+        );
+    }
+
+    #[test]
+    fn multi_dimensional_accesses() {
+        let source = "
+            template T() {
+                signal input m[2][4];
+                signal input c[2][3][2];
+                signal output out;
+                signal s[3][2][2];
+                var v[2][2][2];
+                v[1][0][1] = 5;
+                s[2][1][0] <== m[1][3] * c[1][2][1];
+                out <== s[2][1][0] + c[0][1][0] * v[1][0][1];
+            }";
+        let names = compile(source, "T()").unwrap().public_inputs();
+        assert_eq!(names.len(), 20);
+
+        // Every input needs a value, the ones not read by the output are set to 100
+        let values = HashMap::from([("m[1][3]", 2), ("c[1][2][1]", 3), ("c[0][1][0]", 4)]);
+        let inputs: Vec<(&str, i64)> = names
+            .keys()
+            .map(|name| (name.as_str(), *values.get(name.as_str()).unwrap_or(&100)))
+            .collect();
+        assert_eq!(evaluate(source, "T()", &inputs), BigInt::from(26));
+    }
+
+    #[test]
+    fn hints_compute_values_without_constraining_them() {
+        let source = "
+            template T() {
+                signal input a;
+                signal output out;
+                signal inv;
+                inv <-- 1 / a;
+                inv * a === 1;
+                out <== inv;
+            }";
+        let circuit = compile(source, "T()").unwrap();
+
+        assert_eq!(
+            circuit
+                .gates_iter()
+                .filter(|gate| circuit.is_hint_gate(gate.get_id()))
+                .count(),
+            1
+        );
+        let inverse = evaluate(source, "T()", &[("a", 4)]);
+        assert_eq!(
+            modulo(&(inverse * BigInt::from(4)), &bn254_prime()),
+            BigInt::one()
+        );
+    }
+
+    #[test]
+    fn signals_can_only_be_assigned_once() {
+        let template = |assignments: &str| {
+            format!(
+                "template T() {{ signal input a; signal output out[2]; {} }}",
+                assignments
+            )
+        };
+
+        for assignments in [
+            "out[0] <== a; out[1] <== a; out[0] <== a * a;",
+            "out[0] <-- a; out[1] <== a; out[0] <== a + 1;",
+            "for (var i = 0; i < 3; i++) { out[i % 2] <== a + i; }",
+        ] {
+            match compile(&template(assignments), "T()") {
+                Err(ProgramError::SignalAlreadyAssigned { name, .. }) => {
+                    assert_eq!(name, "out[0]")
+                }
+                result => panic!("expected a reassignment error, got {:?}", result.err()),
             }
         }
-        ExpressionInfixOpcode::BitOr => lhs | rhs,
-        ExpressionInfixOpcode::BitAnd => lhs & rhs,
-        ExpressionInfixOpcode::BitXor => lhs ^ rhs,
-    };
+        assert!(compile(&template("out[0] <== a; out[1] <== a * a;"), "T()").is_ok());
+    }
+
+    #[test]
+    fn component_arrays() {
+        let source = "
+            template Add() {
+                signal input a;
+                signal input b;
+                signal output out;
+                out <== a + b;
+            }
+            template T() {
+                signal input x;
+                signal output out;
+                component adders[3];
+                for (var i = 0; i < 3; i++) {
+                    adders[i] = Add();
+                    adders[i].a <== x;
+                    adders[i].b <== i;
+                }
+                out <== adders[0].out * adders[2].out + adders[1].out;
+            }";
+
+        assert_eq!(evaluate(source, "T()", &[("x", 4)]), BigInt::from(29));
+    }
+
+    #[test]
+    fn underscore_substitution_keeps_the_component() {
+        let source = "
+            template SumProd() {
+                signal input a;
+                signal input b;
+                signal output s;
+                signal output p;
+                s <== a + b;
+                p <== a * b;
+                a * b === 12;
+            }
+            template T() {
+                signal input a;
+                signal input b;
+                signal output out;
+                signal s;
+                _ <== SumProd()(a, b);
+                (s, _) <== SumProd()(a, b);
+                out <== s;
+            }";
+
+        assert_eq!(
+            evaluate(source, "T()", &[("a", 3), ("b", 4)]),
+            BigInt::from(7)
+        );
+        assert!(matches!(
+            try_evaluate(source, "T()", &[("a", 2), ("b", 4)]),
+            Err(CircuitError::ConstraintNotSatisfied(_))
+        ));
+    }
+
+    #[test]
+    fn hinted_signals_copied_by_hints_stay_separate() {
+        let source = "
+            template T() {
+                signal input a;
+                signal output x;
+                signal output y;
+                x <-- 1 / a;
+                y <-- x;
+                x * a === 1;
+            }";
+        let circuit = compile(source, "T()").unwrap();
+        let outputs = circuit.public_outputs();
+        let node_of = |name: &str| circuit.get_signal_node(outputs[name]).unwrap().get_id();
+
+        assert_ne!(node_of("x"), node_of("y"));
+        assert_eq!(
+            circuit
+                .gates_iter()
+                .filter(|gate| circuit.is_hint_gate(gate.get_id()))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn memoized_templates_without_inputs_are_shared() {
+        let source = "pragma circom 2.1.6;
+            template Const(v) {
+                signal output out;
+                signal t;
+                t <-- v;
+                out <== t * t;
+            }
+            template T() {
+                signal output out;
+                component c1 = Const(5);
+                component c2 = Const(5);
+                out <== c1.out + c2.out;
+            }";
+        let compile = |enable_template_memoization: bool| {
+            let config = BuildConfig {
+                enable_template_memoization,
+                ..BuildConfig::default()
+            };
+            compile_circom_source_with_config(source, "T()", config).unwrap()
+        };
+        let (shared, separate) = (compile(true), compile(false));
+
+        // Separate instances each add their gates, a shared one adds them once, and a gate adds the outputs
+        assert_eq!(separate.gate_count() - 1, 2 * (shared.gate_count() - 1));
+        for circuit in [shared, separate] {
+            let values = circuit.simulate(&HashMap::new(), &bn254_prime()).unwrap();
+            assert_eq!(values[&circuit.public_outputs()["out"]], BigInt::from(50));
+        }
+    }
+
+    #[test]
+    fn memoized_templates_run_once() {
+        let source = "pragma circom 2.1.6;
+            template Square() {
+                signal input a;
+                signal output out;
+                log(\"square\");
+                out <== a * a;
+            }
+            template T() {
+                signal input a;
+                signal input b;
+                signal output out;
+                component s1 = Square();
+                component s2 = Square();
+                s1.a <== a;
+                s2.a <== b;
+                out <== s1.out + s2.out;
+            }";
+        let compile = |enable_template_memoization: bool| {
+            let writer = SharedWriter::default();
+            let config = BuildConfig {
+                enable_template_memoization,
+                log_writer: Some(Box::new(writer.clone())),
+                ..BuildConfig::default()
+            };
+            let circuit = compile_circom_source_with_config(source, "T()", config).unwrap();
+            let log = String::from_utf8(writer.0.borrow().clone()).unwrap();
+            (circuit, log)
+        };
+        let output = |circuit: &ArithmeticCircuit| {
+            let ids = circuit.public_inputs();
+            let inputs = HashMap::from([(ids["a"], BigInt::from(3)), (ids["b"], BigInt::from(4))]);
+            let values = circuit.simulate(&inputs, &bn254_prime()).unwrap();
+            values[&circuit.public_outputs()["out"]].clone()
+        };
+        let (memoized, memoized_log) = compile(true);
+        let (original, original_log) = compile(false);
+
+        assert_eq!(memoized_log, "square\n");
+        assert_eq!(original_log, "square\nsquare\n");
+        assert_eq!(output(&memoized), BigInt::from(25));
+        assert_eq!(output(&memoized), output(&original));
+    }
+
+    /// Logger capturing the records of all tests, as the logger is global.
+    struct CapturingLogger(Mutex<Vec<(Level, String)>>);
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
 
-    Ok(res)
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+    #[test]
+    fn statements_and_expressions_are_traced() {
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(LevelFilter::Debug);
+
+        compile(
+            "template T() { signal input a; signal output out; out <== a * 2; }",
+            "T()",
+        )
+        .unwrap();
+
+        let records = LOGGER.0.lock().unwrap();
+        for message in [
+            "Processing substitution statement",
+            "Processing infix operation expression",
+        ] {
+            assert!(records.contains(&(Level::Debug, message.to_string())));
+        }
+    }
 }
@@ -3,30 +3,277 @@
 //! This module processes the circom input program to build the arithmetic circuit.
 
 use crate::{
+    cache::CompilationCache,
     circom::{input::Input, parser::parse_project, type_analysis::analyse_project},
     circuit::{ArithmeticCircuit, CircuitError},
     process::process_statements,
-    runtime::{Runtime, RuntimeError},
+    runtime::{
+        Runtime, RuntimeError, BN254_PRIME, DEFAULT_LOOP_LIMIT, DEFAULT_MAX_RECURSION_DEPTH,
+    },
+};
+use circom_program_structure::ast::{Expression, Meta};
+use circom_program_structure::constants::UsefulConstants;
+use circom_program_structure::program_archive::ProgramArchive;
+use codespan_reporting::files::Files;
+use log::{error, warn};
+use num_bigint_dig::BigInt;
+use std::{
+    env, fmt,
+    io::{self, Write},
+    mem,
 };
-use circom_program_structure::ast::Expression;
-use std::io;
 use thiserror::Error;
 
+/// Environment variable enabling the dump of the context stack when a build fails, if set to `1`.
+pub const DEBUG_ENV_VAR: &str = "CIRCOM_ARITHC_DEBUG";
+
+/// Options controlling how a circuit is built.
+pub struct BuildConfig {
+    /// Prime of the field in which values are computed.
+    pub field_prime: BigInt,
+    /// Maximum number of nested function and template calls.
+    pub max_recursion_depth: usize,
+    /// Maximum number of iterations of a single `while` loop.
+    pub loop_limit: u64,
+    /// Whether unconstrained outputs of the main template are an error rather than a warning.
+    pub strict_outputs: bool,
+    /// Whether gates with constant inputs are evaluated at compile time.
+    pub enable_constant_folding: bool,
+    /// Whether identical gates are merged.
+    pub enable_cse: bool,
+    /// Whether gates and signals not contributing to the outputs are removed.
+    pub enable_dead_signal_elimination: bool,
+    /// Whether warnings are logged for likely mistakes in the program, like unused inputs.
+    pub enable_lints: bool,
+    /// Whether the sub-circuit of each template instance is reused by the later instances with the same parameters,
+    /// without running the template again. Their `log` calls are then skipped. Instances of templates without inputs
+    /// are shared by the later instances rather than reused, as they only depend on their parameters.
+    pub enable_template_memoization: bool,
+    /// Writer receiving the output of circom `log` calls, stdout if not set.
+    pub log_writer: Option<Box<dyn Write>>,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            field_prime: BigInt::parse_bytes(BN254_PRIME.as_bytes(), 10)
+                .expect("BN254 prime is a valid number"),
+            max_recursion_depth: DEFAULT_MAX_RECURSION_DEPTH,
+            loop_limit: DEFAULT_LOOP_LIMIT,
+            strict_outputs: false,
+            enable_constant_folding: false,
+            enable_cse: false,
+            enable_dead_signal_elimination: false,
+            enable_lints: true,
+            enable_template_memoization: false,
+            log_writer: None,
+        }
+    }
+}
+
+impl BuildConfig {
+    /// Creates the default configuration for the options selected in the input.
+    pub fn from_input(input: &Input) -> Self {
+        Self {
+            field_prime: get_prime(input),
+            strict_outputs: input.inspect_constraints_flag,
+            enable_constant_folding: input.fold_constants_flag,
+            enable_cse: input.cse_flag,
+            enable_dead_signal_elimination: input.dead_signal_elimination_flag,
+            ..Self::default()
+        }
+    }
+}
+
 /// Parses a given Circom program and constructs an arithmetic circuit from it.
 pub fn build_circuit(input: &Input) -> Result<ArithmeticCircuit, ProgramError> {
+    build_circuit_with_config(input, BuildConfig::from_input(input))
+}
+
+/// Parses a given Circom program and constructs an arithmetic circuit from it, with the given configuration.
+pub fn build_circuit_with_config(
+    input: &Input,
+    config: BuildConfig,
+) -> Result<ArithmeticCircuit, ProgramError> {
+    let program_archive = parse_project(input).map_err(|_| ProgramError::ParsingError)?;
+
+    build_circuit_from_archive(program_archive, config)
+}
+
+/// Parses a given Circom program and constructs an arithmetic circuit from it, reusing the sub-circuits of the
+/// template instances found in the cache. The sub-circuits of the other instances are added to the cache,
+/// which can be saved to speed up later compilations of programs sharing the same templates.
+pub fn compile_with_cache(
+    input: &Input,
+    cache: &mut CompilationCache,
+) -> Result<ArithmeticCircuit, ProgramError> {
+    let program_archive = parse_project(input).map_err(|_| ProgramError::ParsingError)?;
+
+    build_circuit_with_cache(program_archive, BuildConfig::from_input(input), Some(cache))
+}
+
+/// Constructs an arithmetic circuit from a parsed Circom program, with the given configuration.
+pub fn build_circuit_from_archive(
+    program_archive: ProgramArchive,
+    config: BuildConfig,
+) -> Result<ArithmeticCircuit, ProgramError> {
+    build_circuit_with_cache(program_archive, config, None)
+}
+
+/// Constructs an arithmetic circuit from a parsed Circom program, with an optional cache of template sub-circuits.
+fn build_circuit_with_cache(
+    program_archive: ProgramArchive,
+    mut config: BuildConfig,
+    cache: Option<&mut CompilationCache>,
+) -> Result<ArithmeticCircuit, ProgramError> {
+    // Memoization within a build uses a cache dropped at the end of the build
+    let mut build_cache = CompilationCache::new();
+    let cache = match cache {
+        None if config.enable_template_memoization => Some(&mut build_cache),
+        cache => cache,
+    };
+
+    // The log writer is moved into the runtime, which writes to it, the other options are read from the config
     let mut circuit = ArithmeticCircuit::new();
-    let mut runtime = Runtime::new();
-    let mut program_archive = parse_project(input).map_err(|_| ProgramError::ParsingError)?;
+    let mut runtime = Runtime::with_prime(config.field_prime.clone())?;
+    if let Some(log_writer) = config.log_writer.take() {
+        runtime.set_log_writer(log_writer);
+    }
+
+    // The cache is moved into the runtime for the build, and given back even if it fails
+    let result = match cache {
+        Some(cache) => {
+            runtime.set_compilation_cache(mem::take(cache));
+            let result = process_main(&mut circuit, &mut runtime, program_archive, &config);
+            *cache = runtime.take_compilation_cache().unwrap_or_default();
+            result
+        }
+        None => process_main(&mut circuit, &mut runtime, program_archive, &config),
+    };
+
+    // Failed builds leave the context stack as it was when the error was raised
+    if let Err(e) = &result {
+        if env::var(DEBUG_ENV_VAR).map_or(false, |value| value == "1") {
+            error!("{}\n{}", e, runtime.dump_context_stack());
+        }
+    }
+    result?;
+
+    if config.enable_lints {
+        for warning in circuit.lint_unused_inputs() {
+            warn!("{}", warning);
+        }
+    }
+
+    if config.enable_constant_folding {
+        circuit.fold_constants(runtime.get_prime())?;
+    }
+    if config.enable_cse {
+        circuit.cse()?;
+    }
+    if config.enable_dead_signal_elimination {
+        circuit.eliminate_dead_signals();
+    }
+
+    Ok(circuit)
+}
 
+/// Processes the main template of a Circom program, marking its inputs and outputs in the circuit.
+fn process_main(
+    circuit: &mut ArithmeticCircuit,
+    runtime: &mut Runtime,
+    mut program_archive: ProgramArchive,
+    config: &BuildConfig,
+) -> Result<(), ProgramError> {
     analyse_project(&mut program_archive).map_err(|_| ProgramError::AnalysisError)?;
 
+    let recursive_templates = Runtime::detect_recursive_templates(&program_archive);
+    if !recursive_templates.is_empty() {
+        warn!(
+            "Recursive templates, their parameters must bound the recursion: {}",
+            recursive_templates.join(", ")
+        );
+    }
+
     if let Expression::Call { id, .. } = program_archive.get_main_expression() {
-        let statements = program_archive.get_template_data(id).get_body_as_vec();
+        let template_data = program_archive.get_template_data(id);
+        let statements = template_data.get_body_as_vec();
+
+        process_statements(circuit, runtime, &program_archive, config, statements)?;
 
-        process_statements(&mut circuit, &mut runtime, &program_archive, statements)?;
+        // Mark the inputs and outputs of the main template as the circuit inputs and outputs
+        for (input, _) in template_data.get_declaration_inputs() {
+            let signal = runtime.current_context()?.get_signal(input)?;
+            for signal_id in signal.get_ids() {
+                circuit.mark_input(signal_id)?;
+            }
+        }
+        for (output, _) in template_data.get_declaration_outputs() {
+            let signal = runtime.current_context()?.get_signal(output)?;
+            for signal_id in signal.get_ids() {
+                circuit.mark_output(signal_id)?;
+            }
+        }
+
+        let unconstrained = circuit.check_outputs_constrained()?;
+        if !unconstrained.is_empty() {
+            let names: Vec<String> = unconstrained
+                .iter()
+                .map(|&signal| circuit.signal_label(signal))
+                .collect();
+            if config.strict_outputs {
+                return Err(ProgramError::UnconstrainedOutputs(names.join(", ")));
+            }
+            warn!("Unconstrained output signals: {}", names.join(", "));
+        }
     }
 
-    Ok(circuit)
+    Ok(())
+}
+
+/// Returns the prime of the field selected in the input.
+pub fn get_prime(input: &Input) -> BigInt {
+    UsefulConstants::new(&input.prime()).get_p().clone()
+}
+
+/// Location of an AST node in the circom source files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl SourceLocation {
+    /// Resolves the location of an AST node from its meta information.
+    pub fn from_meta(meta: &Meta, program_archive: &ProgramArchive) -> Option<Self> {
+        let file_id = meta.file_id?;
+        let files = program_archive.get_file_library().to_storage();
+
+        let file = files.name(file_id)?;
+        let line_index = files.line_index(file_id, meta.start)?;
+        let line_start = files.line_range(file_id, line_index)?.start;
+
+        Some(Self {
+            file,
+            line: line_index as u32 + 1,
+            col: (meta.start - line_start) as u32 + 1,
+        })
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+/// Formats an optional source location as an error message suffix.
+fn display_location(location: &Option<SourceLocation>) -> String {
+    match location {
+        Some(location) => format!(" at {}", location),
+        None => String::new(),
+    }
 }
 
 /// Program errors
@@ -34,30 +281,86 @@ pub fn build_circuit(input: &Input) -> Result<ArithmeticCircuit, ProgramError> {
 pub enum ProgramError {
     #[error("Analysis error")]
     AnalysisError,
+    #[error("Assertion failed{}", display_location(.location))]
+    AssertionFailed { location: Option<SourceLocation> },
+    #[error("Cache serialization error: {0}")]
+    CacheSerializationError(#[from] bincode::Error),
     #[error("Call error")]
     CallError,
     #[error("Circuit error: {0}")]
     CircuitError(CircuitError),
-    #[error("Empty data item")]
-    EmptyDataItem,
+    #[error("Constraint error: {0}")]
+    ConstraintError(String),
+    #[error("Empty data item{}", display_location(.location))]
+    EmptyDataItem { location: Option<SourceLocation> },
     #[error("Input initialization error")]
     InputInitializationError,
     #[error("Invalid data type")]
     InvalidDataType,
+    #[error("Invalid dimension")]
+    InvalidDimension,
     #[error("IO error: {0}")]
     IOError(#[from] io::Error),
     #[error("JSON serialization error: {0}")]
     JsonSerializationError(#[from] serde_json::Error),
-    #[error("Operation error: {0}")]
-    OperationError(String),
-    #[error("Operation not supported")]
-    OperationNotSupported,
+    #[error("Not implemented: {0}")]
+    NotImplemented(String),
+    #[error("Operation error: {message}{}", display_location(.location))]
+    OperationError {
+        message: String,
+        location: Option<SourceLocation>,
+    },
+    #[error("Operation not supported{}", display_location(.location))]
+    OperationNotSupported { location: Option<SourceLocation> },
     #[error("Output directory creation error")]
     OutputDirectoryCreationError,
     #[error("Parsing error")]
     ParsingError,
+    #[error("Recursion depth {depth} exceeded in {template_name}")]
+    RecursionDepthExceeded { template_name: String, depth: usize },
     #[error("Runtime error: {0}")]
     RuntimeError(RuntimeError),
-    #[error("Undefined function or template")]
-    UndefinedFunctionOrTemplate,
+    #[error("Signal {name} already assigned{}", display_location(.location))]
+    SignalAlreadyAssigned {
+        name: String,
+        location: Option<SourceLocation>,
+    },
+    #[error(
+        "Signal {signal_name} not found in {component_name}, available signals: {}{}",
+        .available_signals.join(", "),
+        display_location(.location)
+    )]
+    SignalNotFound {
+        signal_name: String,
+        component_name: String,
+        available_signals: Vec<String>,
+        location: Option<SourceLocation>,
+    },
+    #[error("Unconstrained output signals: {0}")]
+    UnconstrainedOutputs(String),
+    #[error("Undefined function or template{}", display_location(.location))]
+    UndefinedFunctionOrTemplate { location: Option<SourceLocation> },
+}
+
+impl ProgramError {
+    /// Sets the source location of the error if it can hold one and it isn't set yet.
+    /// Errors are located by the innermost statement or expression that raised them.
+    pub fn with_location(mut self, new_location: Option<SourceLocation>) -> Self {
+        match &mut self {
+            ProgramError::AssertionFailed { location }
+            | ProgramError::EmptyDataItem { location }
+            | ProgramError::OperationError { location, .. }
+            | ProgramError::OperationNotSupported { location }
+            | ProgramError::SignalAlreadyAssigned { location, .. }
+            | ProgramError::SignalNotFound { location, .. }
+            | ProgramError::UndefinedFunctionOrTemplate { location } => {
+                if location.is_none() {
+                    *location = new_location;
+                }
+            }
+            _ => {}
+        }
+
+        self
+    }
 }
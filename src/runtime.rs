@@ -2,17 +2,35 @@
 //!
 //! This module manages the main runtime, keeping track of the multiple contexts and data items in the program.
 
+use crate::cache::{CompilationCache, SubCircuit};
 use crate::program::ProgramError;
-use circom_program_structure::ast::VariableType;
+use circom_circom_algebra::num_traits::One;
+use circom_program_structure::ast::{Access, Expression, LogArgument, Statement, VariableType};
+use circom_program_structure::program_archive::ProgramArchive;
+use num_bigint_dig::BigInt;
 use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
 use thiserror::Error;
 
 pub const RETURN_VAR: &str = "function_return_value";
 
+/// Prime of the BN254 scalar field, used by default for field element arithmetic.
+pub const BN254_PRIME: &str =
+    "21888242871839275222246405745257274088548364400416034343698204186575808495617";
+
+/// Default maximum number of iterations of a single `while` loop, to stop non terminating loops.
+pub const DEFAULT_LOOP_LIMIT: u64 = 1_000_000;
+
+/// Default maximum number of nested function and template calls, to stop non terminating recursions.
+pub const DEFAULT_MAX_RECURSION_DEPTH: usize = 512;
+
 /// Data type
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DataType {
+    Bus,
     Component,
     Signal,
     Variable,
@@ -22,6 +40,7 @@ impl TryFrom<&VariableType> for DataType {
     type Error = RuntimeError;
     fn try_from(t: &VariableType) -> Result<Self, Self::Error> {
         match t {
+            VariableType::Bus(..) => Ok(DataType::Bus),
             VariableType::Component => Ok(DataType::Component),
             VariableType::Signal(..) => Ok(DataType::Signal),
             VariableType::Var => Ok(DataType::Variable),
@@ -31,7 +50,7 @@ impl TryFrom<&VariableType> for DataType {
 }
 
 /// Structure to hold either a single or a nested array of values.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NestedValue<T> {
     Array(Vec<NestedValue<T>>),
     Value(T),
@@ -46,9 +65,18 @@ pub enum SubAccess {
     Component(String),
 }
 
+/// Return value of a function call, as the dimensions of the returned item and its flattened values.
+pub type FunctionReturn = (Vec<u32>, Vec<Option<BigInt>>);
+
 /// Manages a stack of execution contexts for a runtime environment.
 pub struct Runtime {
     contexts: VecDeque<Context>,
+    log_writer: Box<dyn Write>,
+    prime: BigInt,
+    call_stack: Vec<String>,
+    template_cache: HashMap<(String, Vec<BigInt>), HashMap<String, Signal>>,
+    function_cache: HashMap<(String, Vec<BigInt>), FunctionReturn>,
+    compilation_cache: Option<CompilationCache>,
 }
 
 impl Default for Runtime {
@@ -62,7 +90,179 @@ impl Runtime {
     pub fn new() -> Self {
         Self {
             contexts: VecDeque::from([Context::new()]),
+            log_writer: Box::new(io::stdout()),
+            prime: BigInt::parse_bytes(BN254_PRIME.as_bytes(), 10)
+                .expect("BN254 prime is a valid number"),
+            call_stack: Vec::new(),
+            template_cache: HashMap::new(),
+            function_cache: HashMap::new(),
+            compilation_cache: None,
+        }
+    }
+
+    /// Creates a runtime that computes variable values in the field of the given prime.
+    pub fn with_prime(prime: BigInt) -> Result<Self, RuntimeError> {
+        // Only a cheap sanity check, the prime is expected to come from a known curve
+        if prime <= BigInt::from(2u32) || (&prime & BigInt::one()) != BigInt::one() {
+            return Err(RuntimeError::InvalidPrime(prime.to_string()));
+        }
+
+        Ok(Self {
+            prime,
+            ..Self::new()
+        })
+    }
+
+    /// Returns the prime of the field in which variable values are computed.
+    pub fn get_prime(&self) -> &BigInt {
+        &self.prime
+    }
+
+    /// Enters a function or template call, failing if the given maximum number of nested calls is exceeded.
+    pub fn enter_call(
+        &mut self,
+        name: &str,
+        max_recursion_depth: usize,
+    ) -> Result<(), ProgramError> {
+        if self.call_stack.len() >= max_recursion_depth {
+            return Err(ProgramError::RecursionDepthExceeded {
+                template_name: name.to_string(),
+                depth: self.call_stack.len(),
+            });
         }
+
+        self.call_stack.push(name.to_string());
+        Ok(())
+    }
+
+    /// Leaves the current function or template call.
+    pub fn exit_call(&mut self) {
+        self.call_stack.pop();
+    }
+
+    /// Returns the name of the innermost function or template call, if any.
+    pub fn current_call(&self) -> Option<&str> {
+        self.call_stack.last().map(String::as_str)
+    }
+
+    /// Returns the sorted names of the templates that instantiate themselves, directly or through other templates.
+    /// Such templates only terminate if their parameters bound the recursion.
+    pub fn detect_recursive_templates(program_archive: &ProgramArchive) -> Vec<String> {
+        let calls: HashMap<String, HashSet<String>> = program_archive
+            .get_templates()
+            .iter()
+            .map(|(name, template_data)| {
+                let mut called = HashSet::new();
+                collect_statement_calls(template_data.get_body(), &mut called);
+                called.retain(|id| program_archive.contains_template(id));
+                (name.clone(), called)
+            })
+            .collect();
+
+        let mut recursive: Vec<String> = calls
+            .keys()
+            .filter(|name| {
+                // Look for the template among the templates reachable from it
+                let mut visited = HashSet::new();
+                let mut stack: Vec<&String> = calls[*name].iter().collect();
+                while let Some(called) = stack.pop() {
+                    if called == *name {
+                        return true;
+                    }
+                    if visited.insert(called) {
+                        stack.extend(calls.get(called).into_iter().flatten());
+                    }
+                }
+                false
+            })
+            .cloned()
+            .collect();
+
+        recursive.sort();
+        recursive
+    }
+
+    /// Returns the signals of a cached template instance with the given parameters.
+    pub fn get_cached_template(
+        &self,
+        name: &str,
+        params: &[BigInt],
+    ) -> Option<HashMap<String, Signal>> {
+        self.template_cache
+            .get(&(name.to_string(), params.to_vec()))
+            .cloned()
+    }
+
+    /// Caches the signals of a template instance with the given parameters.
+    pub fn cache_template(
+        &mut self,
+        name: &str,
+        params: &[BigInt],
+        signals: HashMap<String, Signal>,
+    ) {
+        self.template_cache
+            .insert((name.to_string(), params.to_vec()), signals);
+    }
+
+    /// Returns the cached return value of a function called with the given arguments.
+    pub fn get_cached_function(&self, name: &str, args: &[BigInt]) -> Option<FunctionReturn> {
+        self.function_cache
+            .get(&(name.to_string(), args.to_vec()))
+            .cloned()
+    }
+
+    /// Caches the return value of a function called with the given arguments.
+    pub fn cache_function(&mut self, name: &str, args: &[BigInt], function_return: FunctionReturn) {
+        self.function_cache
+            .insert((name.to_string(), args.to_vec()), function_return);
+    }
+
+    /// Sets the cache of template sub-circuits shared across compilations.
+    pub fn set_compilation_cache(&mut self, mut cache: CompilationCache) {
+        cache.set_prime(&self.prime);
+        self.compilation_cache = Some(cache);
+    }
+
+    /// Removes the cache of template sub-circuits from the runtime, returning it.
+    pub fn take_compilation_cache(&mut self) -> Option<CompilationCache> {
+        self.compilation_cache.take()
+    }
+
+    /// Checks if template sub-circuits are cached across compilations.
+    pub fn has_compilation_cache(&self) -> bool {
+        self.compilation_cache.is_some()
+    }
+
+    /// Returns the cached sub-circuit of a template instance with the given parameters.
+    pub fn get_compiled_template(&self, name: &str, params: &[BigInt]) -> Option<&SubCircuit> {
+        self.compilation_cache.as_ref()?.get(name, params)
+    }
+
+    /// Caches the sub-circuit of a template instance with the given parameters, if a compilation cache is set.
+    pub fn cache_compiled_template(
+        &mut self,
+        name: &str,
+        params: &[BigInt],
+        sub_circuit: SubCircuit,
+    ) {
+        if let Some(cache) = self.compilation_cache.as_mut() {
+            cache.insert(name, params, sub_circuit);
+        }
+    }
+
+    /// Sets the writer that receives the output of circom `log` calls. Defaults to stdout.
+    pub fn set_log_writer(&mut self, writer: Box<dyn Write>) {
+        self.log_writer = writer;
+    }
+
+    /// Discards the output of circom `log` calls.
+    pub fn suppress_logs(&mut self) {
+        self.log_writer = Box::new(io::sink());
+    }
+
+    /// Returns the writer that receives the output of circom `log` calls.
+    pub fn log_writer(&mut self) -> &mut dyn Write {
+        self.log_writer.as_mut()
     }
 
     /// Adds a new context onto the stack, optionally inheriting from the current context.
@@ -79,6 +279,13 @@ impl Runtime {
         Ok(())
     }
 
+    /// Adds a new context for a loop onto the stack, inheriting from the current context.
+    pub fn push_loop_context(&mut self) -> Result<(), RuntimeError> {
+        self.push_context(true)?;
+        self.current_context()?.is_loop = true;
+        Ok(())
+    }
+
     /// Removes the current context from the stack, with an option to merge it into its parent.
     pub fn pop_context(&mut self, merge: bool) -> Result<(), RuntimeError> {
         if self.contexts.is_empty() {
@@ -108,6 +315,131 @@ impl Runtime {
             .front_mut()
             .ok_or(RuntimeError::EmptyContextStack)
     }
+
+    /// Returns a readable trace of the context stack, from the outermost to the current context, with the values of
+    /// the variables and the names of the signals of each context. Used to debug non terminating loops and calls.
+    pub fn dump_context_stack(&self) -> String {
+        let mut dump = String::new();
+        for (depth, context) in self.contexts.iter().rev().enumerate() {
+            // Writing to a string can't fail
+            let _ = context.dump(depth, &mut dump);
+        }
+
+        dump
+    }
+}
+
+/// Collects the names of the functions and templates called in a statement.
+fn collect_statement_calls(statement: &Statement, calls: &mut HashSet<String>) {
+    match statement {
+        Statement::IfThenElse {
+            cond,
+            if_case,
+            else_case,
+            ..
+        } => {
+            collect_expression_calls(cond, calls);
+            collect_statement_calls(if_case, calls);
+            if let Some(else_case) = else_case {
+                collect_statement_calls(else_case, calls);
+            }
+        }
+        Statement::While { cond, stmt, .. } => {
+            collect_expression_calls(cond, calls);
+            collect_statement_calls(stmt, calls);
+        }
+        Statement::Return { value, .. } => collect_expression_calls(value, calls),
+        Statement::InitializationBlock {
+            initializations, ..
+        } => initializations
+            .iter()
+            .for_each(|stmt| collect_statement_calls(stmt, calls)),
+        Statement::Block { stmts, .. } => stmts
+            .iter()
+            .for_each(|stmt| collect_statement_calls(stmt, calls)),
+        Statement::Declaration { dimensions, .. } => dimensions
+            .iter()
+            .for_each(|expression| collect_expression_calls(expression, calls)),
+        Statement::Substitution { access, rhe, .. } => {
+            collect_access_calls(access, calls);
+            collect_expression_calls(rhe, calls);
+        }
+        Statement::MultSubstitution { lhe, rhe, .. }
+        | Statement::ConstraintEquality { lhe, rhe, .. } => {
+            collect_expression_calls(lhe, calls);
+            collect_expression_calls(rhe, calls);
+        }
+        Statement::UnderscoreSubstitution { rhe, .. } => collect_expression_calls(rhe, calls),
+        Statement::LogCall { args, .. } => {
+            for arg in args {
+                if let LogArgument::LogExp(expression) = arg {
+                    collect_expression_calls(expression, calls);
+                }
+            }
+        }
+        Statement::Assert { arg, .. } => collect_expression_calls(arg, calls),
+        _ => {}
+    }
+}
+
+/// Collects the names of the functions and templates called in an expression.
+fn collect_expression_calls(expression: &Expression, calls: &mut HashSet<String>) {
+    match expression {
+        Expression::InfixOp { lhe, rhe, .. } => {
+            collect_expression_calls(lhe, calls);
+            collect_expression_calls(rhe, calls);
+        }
+        Expression::PrefixOp { rhe, .. } | Expression::ParallelOp { rhe, .. } => {
+            collect_expression_calls(rhe, calls)
+        }
+        Expression::InlineSwitchOp {
+            cond,
+            if_true,
+            if_false,
+            ..
+        } => {
+            collect_expression_calls(cond, calls);
+            collect_expression_calls(if_true, calls);
+            collect_expression_calls(if_false, calls);
+        }
+        Expression::Variable { access, .. } => collect_access_calls(access, calls),
+        Expression::Call { id, args, .. } => {
+            calls.insert(id.clone());
+            args.iter()
+                .for_each(|arg| collect_expression_calls(arg, calls));
+        }
+        Expression::AnonymousComp {
+            id,
+            params,
+            signals,
+            ..
+        } => {
+            calls.insert(id.clone());
+            params
+                .iter()
+                .chain(signals.iter())
+                .for_each(|arg| collect_expression_calls(arg, calls));
+        }
+        Expression::ArrayInLine { values, .. } | Expression::Tuple { values, .. } => values
+            .iter()
+            .for_each(|value| collect_expression_calls(value, calls)),
+        Expression::UniformArray {
+            value, dimension, ..
+        } => {
+            collect_expression_calls(value, calls);
+            collect_expression_calls(dimension, calls);
+        }
+        _ => {}
+    }
+}
+
+/// Collects the names of the functions and templates called in the indices of an access.
+fn collect_access_calls(access: &[Access], calls: &mut HashSet<String>) {
+    for access in access {
+        if let Access::ArrayAccess(expression) = access {
+            collect_expression_calls(expression, calls);
+        }
+    }
 }
 
 /// Context
@@ -118,6 +450,8 @@ pub struct Context {
     variables: HashMap<String, Variable>,
     signals: HashMap<String, Signal>,
     components: HashMap<String, Component>,
+    buses: HashSet<String>,
+    is_loop: bool,
 }
 
 impl Default for Context {
@@ -134,6 +468,8 @@ impl Context {
             variables: HashMap::new(),
             signals: HashMap::new(),
             components: HashMap::new(),
+            buses: HashSet::new(),
+            is_loop: false,
         }
     }
 
@@ -144,7 +480,43 @@ impl Context {
             variables: self.variables.clone(),
             signals: self.signals.clone(),
             components: self.components.clone(),
+            buses: self.buses.clone(),
+            is_loop: false,
+        }
+    }
+
+    /// Checks if the context holds the iterations of a loop.
+    pub fn is_loop(&self) -> bool {
+        self.is_loop
+    }
+
+    /// Writes the variables and signals of the context at the given depth of the stack.
+    fn dump(&self, depth: usize, out: &mut String) -> fmt::Result {
+        writeln!(
+            out,
+            "Context {}{}",
+            depth,
+            if self.is_loop { " (loop)" } else { "" }
+        )?;
+
+        let mut variables: Vec<(&String, &Variable)> = self.variables.iter().collect();
+        variables.sort_by_key(|(name, _)| *name);
+        for (name, variable) in variables {
+            writeln!(
+                out,
+                "  var {} = {}",
+                name,
+                format_nested_value(&variable.value)
+            )?;
+        }
+
+        let mut signals: Vec<&String> = self.signals.keys().collect();
+        signals.sort();
+        for name in signals {
+            writeln!(out, "  signal {}", name)?;
         }
+
+        Ok(())
     }
 
     /// Merges changes from the given context into this context.
@@ -199,6 +571,12 @@ impl Context {
                 let component = Component::new(dimensions);
                 self.components.insert(name, component);
             }
+            DataType::Bus => {
+                // A bus is stored as a component holding its field signals
+                let bus = Component::new(dimensions);
+                self.components.insert(name.clone(), bus);
+                self.buses.insert(name);
+            }
         };
 
         Ok(())
@@ -206,17 +584,68 @@ impl Context {
 
     /// Declares a new item with a random name.
     pub fn declare_random_item(&mut self, data_type: DataType) -> Result<DataAccess, RuntimeError> {
+        self.declare_random_array(data_type, &[])
+    }
+
+    /// Declares a new item with a random name and the given dimensions.
+    pub fn declare_random_array(
+        &mut self,
+        data_type: DataType,
+        dimensions: &[u32],
+    ) -> Result<DataAccess, RuntimeError> {
         let name = format!("random_{}", generate_u32());
-        self.declare_item(data_type, &name, &[])?;
+        self.declare_item(data_type, &name, dimensions)?;
         Ok(DataAccess::new(&name, vec![]))
     }
 
+    /// Returns the dimensions of the item at the specified access.
+    /// An access to a single value has no dimensions.
+    pub fn get_item_dimensions(&self, access: &DataAccess) -> Result<Vec<u32>, RuntimeError> {
+        let not_declared =
+            || RuntimeError::ItemNotDeclared(format!("get_item_dimensions: {:?}", access));
+
+        match self.get_item_data_type(&access.name)? {
+            DataType::Variable => {
+                let variable = self.variables.get(&access.name).ok_or_else(not_declared)?;
+                get_nested_dimensions(&variable.value, &access_to_u32(access.get_access())?)
+            }
+            DataType::Signal => {
+                let signal = self.signals.get(&access.name).ok_or_else(not_declared)?;
+                get_nested_dimensions(&signal.value, &access_to_u32(access.get_access())?)
+            }
+            DataType::Component | DataType::Bus => {
+                let component = self.components.get(&access.name).ok_or_else(not_declared)?;
+
+                // Without a signal access, the dimensions are those of the component array
+                if !access
+                    .get_access()
+                    .iter()
+                    .any(|sub_access| matches!(sub_access, SubAccess::Component(_)))
+                {
+                    return get_nested_dimensions(
+                        &component.signal_map,
+                        &access_to_u32(access.get_access())?,
+                    );
+                }
+
+                let (component_access, signal_access) = process_component_access(access)?;
+                let map = component.get_map(&access_to_u32(component_access.get_access())?)?;
+                let signal = map.get(&signal_access.get_name()).ok_or_else(|| {
+                    signal_not_found(&signal_access.get_name(), &component_access.name, &map)
+                })?;
+                get_nested_dimensions(&signal.value, &access_to_u32(signal_access.get_access())?)
+            }
+        }
+    }
+
     /// Returns the data type of an item.
     pub fn get_item_data_type(&self, name: &str) -> Result<DataType, RuntimeError> {
         if self.variables.get(name).is_some() {
             Ok(DataType::Variable)
         } else if self.signals.get(name).is_some() {
             Ok(DataType::Signal)
+        } else if self.buses.contains(name) {
+            Ok(DataType::Bus)
         } else if self.components.get(name).is_some() {
             Ok(DataType::Component)
         } else {
@@ -231,7 +660,7 @@ impl Context {
     pub fn set_variable(
         &mut self,
         access: &DataAccess,
-        value: Option<u32>,
+        value: Option<BigInt>,
     ) -> Result<(), RuntimeError> {
         let variable =
             self.variables
@@ -245,7 +674,7 @@ impl Context {
     }
 
     /// Gets the content of a variable.
-    pub fn get_variable_value(&self, access: &DataAccess) -> Result<Option<u32>, RuntimeError> {
+    pub fn get_variable_value(&self, access: &DataAccess) -> Result<Option<BigInt>, RuntimeError> {
         let variable = self
             .variables
             .get(&access.name)
@@ -268,6 +697,11 @@ impl Context {
             .map(|signal| signal.clone())
     }
 
+    /// Gets all the signals declared in the context.
+    pub fn get_signals(&self) -> HashMap<String, Signal> {
+        self.signals.clone()
+    }
+
     /// Gets the id of the signal at the specified index path.
     pub fn get_signal_id(&self, access: &DataAccess) -> Result<u32, RuntimeError> {
         let signal = self
@@ -309,11 +743,39 @@ impl Context {
                 )))?;
 
         component.get_signal_id(
+            &component_access.name,
             &access_to_u32(component_access.get_access())?,
             &signal_access,
         )
     }
 
+    /// Marks a component as instantiated with the `parallel` keyword.
+    pub fn mark_parallel(&mut self, access: &DataAccess) -> Result<(), RuntimeError> {
+        let component =
+            self.components
+                .get_mut(&access.name)
+                .ok_or(RuntimeError::ItemNotDeclared(format!(
+                    "mark_parallel: {:?}",
+                    access
+                )))?;
+
+        component.parallel = true;
+        Ok(())
+    }
+
+    /// Returns whether a component was instantiated with the `parallel` keyword.
+    pub fn is_parallel(&self, access: &DataAccess) -> Result<bool, RuntimeError> {
+        let component = self
+            .components
+            .get(&access.name)
+            .ok_or(RuntimeError::ItemNotDeclared(format!(
+                "is_parallel: {:?}",
+                access
+            )))?;
+
+        Ok(component.parallel)
+    }
+
     /// Sets a component's input/output signal map.
     pub fn set_component(
         &mut self,
@@ -333,7 +795,7 @@ impl Context {
 }
 
 /// Represents a signal that holds a single id or a nested structure of values with unique IDs.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Signal {
     value: NestedValue<u32>,
 }
@@ -355,6 +817,46 @@ impl Signal {
         }
     }
 
+    /// Constructs a Signal from existing signal ids.
+    pub fn from_value(value: NestedValue<u32>) -> Self {
+        Self { value }
+    }
+
+    /// Returns the ids of all the signals, in index order.
+    pub fn get_ids(&self) -> Vec<u32> {
+        fn collect_ids(value: &NestedValue<u32>, ids: &mut Vec<u32>) {
+            match value {
+                NestedValue::Array(values) => {
+                    values.iter().for_each(|value| collect_ids(value, ids))
+                }
+                NestedValue::Value(id) => ids.push(*id),
+            }
+        }
+
+        let mut ids = Vec::new();
+        collect_ids(&self.value, &mut ids);
+        ids
+    }
+
+    /// Returns the signal with its ids replaced by the given ones, failing if an id isn't mapped.
+    pub fn remap(&self, ids: &HashMap<u32, u32>) -> Option<Self> {
+        fn remap_ids(
+            value: &NestedValue<u32>,
+            ids: &HashMap<u32, u32>,
+        ) -> Option<NestedValue<u32>> {
+            match value {
+                NestedValue::Array(values) => values
+                    .iter()
+                    .map(|value| remap_ids(value, ids))
+                    .collect::<Option<Vec<_>>>()
+                    .map(NestedValue::Array),
+                NestedValue::Value(id) => ids.get(id).copied().map(NestedValue::Value),
+            }
+        }
+
+        remap_ids(&self.value, ids).map(Self::from_value)
+    }
+
     /// Retrieves the ID of the signal at the specified index path.
     fn get(&self, index_path: &[u32]) -> Result<u32, RuntimeError> {
         get_nested_value(&self.value, index_path)
@@ -364,7 +866,7 @@ impl Signal {
 /// Represents a variable that can hold a single value or nested structure of values.
 #[derive(Clone, Debug)]
 pub struct Variable {
-    value: NestedValue<Option<u32>>,
+    value: NestedValue<Option<BigInt>>,
 }
 
 impl Variable {
@@ -383,14 +885,14 @@ impl Variable {
     }
 
     /// Sets the content of the variable at the specified index path.
-    fn set(&mut self, index_path: &[u32], val: Option<u32>) -> Result<(), RuntimeError> {
+    fn set(&mut self, index_path: &[u32], val: Option<BigInt>) -> Result<(), RuntimeError> {
         let inner_value = get_mut_nested_value(&mut self.value, index_path)?;
         *inner_value = val;
         Ok(())
     }
 
     /// Retrieves the content of the variable at the specified index path.
-    fn get(&self, index_path: &[u32]) -> Result<Option<u32>, RuntimeError> {
+    fn get(&self, index_path: &[u32]) -> Result<Option<BigInt>, RuntimeError> {
         get_nested_value(&self.value, index_path)
     }
 }
@@ -399,6 +901,7 @@ impl Variable {
 #[derive(Clone, Debug)]
 pub struct Component {
     signal_map: NestedValue<HashMap<String, Signal>>,
+    parallel: bool,
 }
 
 impl Component {
@@ -412,7 +915,10 @@ impl Component {
             signal_map = NestedValue::Array(array);
         }
 
-        Self { signal_map }
+        Self {
+            signal_map,
+            parallel: false,
+        }
     }
 
     /// Retrieves the component signal map at the specified index path.
@@ -435,16 +941,14 @@ impl Component {
     /// Returns the signal's ID at the specified index path.
     fn get_signal_id(
         &self,
+        component_name: &str,
         component_access: &[u32],
         signal_access: &DataAccess,
     ) -> Result<u32, RuntimeError> {
         let map = get_nested_value(&self.signal_map, component_access)?;
         let signal = map
             .get(&signal_access.get_name())
-            .ok_or(RuntimeError::ItemNotDeclared(format!(
-                "get_signal_id: {:?}",
-                signal_access
-            )))?;
+            .ok_or_else(|| signal_not_found(&signal_access.get_name(), component_name, &map))?;
 
         signal.get(&access_to_u32(signal_access.get_access())?)
     }
@@ -530,6 +1034,18 @@ pub fn process_component_access(
     ))
 }
 
+/// Formats the values of a variable, with `[...]` for arrays and `_` for values not set yet.
+fn format_nested_value(value: &NestedValue<Option<BigInt>>) -> String {
+    match value {
+        NestedValue::Array(values) => {
+            let values: Vec<String> = values.iter().map(format_nested_value).collect();
+            format!("[{}]", values.join(", "))
+        }
+        NestedValue::Value(Some(value)) => value.to_string(),
+        NestedValue::Value(None) => "_".to_string(),
+    }
+}
+
 /// Generic function to navigate through NestedValue and return the inner value.
 /// The clone could be removed but then the type would need to implement Copy. Leaving it for now.
 pub fn get_nested_value<T: Clone>(
@@ -554,6 +1070,35 @@ pub fn get_nested_value<T: Clone>(
     }
 }
 
+/// Generic function to navigate through NestedValue and return the dimensions of the nested structure found.
+pub fn get_nested_dimensions<T>(
+    nested_value: &NestedValue<T>,
+    index_path: &[u32],
+) -> Result<Vec<u32>, RuntimeError> {
+    let mut current_level = nested_value;
+    for &index in index_path {
+        match current_level {
+            NestedValue::Array(values) => {
+                current_level = values
+                    .get(index as usize)
+                    .ok_or(RuntimeError::IndexOutOfBounds)?;
+            }
+            _ => return Err(RuntimeError::AccessError),
+        }
+    }
+
+    let mut dimensions = Vec::new();
+    while let NestedValue::Array(values) = current_level {
+        dimensions.push(values.len() as u32);
+        match values.first() {
+            Some(value) => current_level = value,
+            None => break,
+        }
+    }
+
+    Ok(dimensions)
+}
+
 /// Generic function to navigate through NestedValue and return a mutable reference to the inner value.
 pub fn get_mut_nested_value<'a, T>(
     nested_value: &'a mut NestedValue<T>,
@@ -619,6 +1164,22 @@ pub fn increment_indices(indices: &mut Vec<u32>, limits: &[u32]) -> Result<bool,
     Ok(!carry)
 }
 
+/// Returns the error for a signal missing from a component, listing the signals the component has.
+pub fn signal_not_found(
+    signal_name: &str,
+    component_name: &str,
+    signal_map: &HashMap<String, Signal>,
+) -> RuntimeError {
+    let mut available_signals: Vec<String> = signal_map.keys().cloned().collect();
+    available_signals.sort();
+
+    RuntimeError::SignalNotFound {
+        signal_name: signal_name.to_string(),
+        component_name: component_name.to_string(),
+        available_signals,
+    }
+}
+
 /// Generates a random u32.
 pub fn generate_u32() -> u32 {
     thread_rng().gen()
@@ -634,6 +1195,8 @@ pub enum RuntimeError {
     EmptyContextStack,
     #[error("Index out of bounds")]
     IndexOutOfBounds,
+    #[error("Invalid prime: {0}")]
+    InvalidPrime(String),
     #[error("Item already declared")]
     ItemAlreadyDeclared,
     #[error("Item not declared: {0}")]
@@ -642,12 +1205,55 @@ pub enum RuntimeError {
     NoContextToInheritFrom,
     #[error("Data Item content is not a single value")]
     NotAValue,
+    #[error(
+        "Signal {signal_name} not found in {component_name}, available signals: {}",
+        .available_signals.join(", ")
+    )]
+    SignalNotFound {
+        signal_name: String,
+        component_name: String,
+        available_signals: Vec<String>,
+    },
     #[error("Unsupported data type")]
     UnsupportedDataType,
 }
 
 impl From<RuntimeError> for ProgramError {
     fn from(e: RuntimeError) -> Self {
-        ProgramError::RuntimeError(e)
+        match e {
+            // Missing component signals are usually misspelled, so they're reported as program errors
+            RuntimeError::SignalNotFound {
+                signal_name,
+                component_name,
+                available_signals,
+            } => ProgramError::SignalNotFound {
+                signal_name,
+                component_name,
+                available_signals,
+                location: None,
+            },
+            e => ProgramError::RuntimeError(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_prime_rejects_invalid_primes() {
+        assert!(matches!(
+            Runtime::with_prime(BigInt::from(2)),
+            Err(RuntimeError::InvalidPrime(_))
+        ));
+        assert!(matches!(
+            Runtime::with_prime(BigInt::from(18)),
+            Err(RuntimeError::InvalidPrime(_))
+        ));
+        assert_eq!(
+            Runtime::with_prime(BigInt::from(17)).unwrap().get_prime(),
+            &BigInt::from(17)
+        );
     }
 }
@@ -0,0 +1,46 @@
+//! # WASM Module
+//!
+//! This module exposes the compiler to JavaScript through `wasm-bindgen`, for browser based tools.
+
+use crate::{compiler::compile_circom_files as compile_files, program::BuildConfig};
+use js_sys::{Array, Object};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+/// Name of the file holding the source given to `compile_circom`.
+const MAIN_FILE: &str = "main.circom";
+
+/// Compiles a circom program declaring its main component, returning its arithmetic circuit as a JSON object.
+/// The source is parsed in memory, as there's no filesystem in the browser. Errors are thrown as strings.
+#[wasm_bindgen]
+pub fn compile_circom(source: &str) -> Result<JsValue, JsValue> {
+    let files = HashMap::from([(MAIN_FILE.to_string(), source.to_string())]);
+    compile(&files, MAIN_FILE)
+}
+
+/// Compiles a circom program split in several files, given as an object mapping their paths to their sources.
+/// Includes are resolved against these paths, relative to the including file, and `main_file` must declare the
+/// main component.
+#[wasm_bindgen]
+pub fn compile_circom_files(files: &Object, main_file: &str) -> Result<JsValue, JsValue> {
+    let files = Object::entries(files)
+        .iter()
+        .map(|entry| {
+            let entry = Array::from(&entry);
+            match (entry.get(0).as_string(), entry.get(1).as_string()) {
+                (Some(path), Some(source)) => Ok((path, source)),
+                _ => Err(JsValue::from_str("Files must map paths to sources")),
+            }
+        })
+        .collect::<Result<HashMap<String, String>, JsValue>>()?;
+
+    compile(&files, main_file)
+}
+
+/// Compiles in-memory files and converts the circuit into a JSON object.
+fn compile(files: &HashMap<String, String>, main_file: &str) -> Result<JsValue, JsValue> {
+    let circuit = compile_files(files, main_file, BuildConfig::default())
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    js_sys::JSON::parse(&circuit.to_json().to_string())
+}